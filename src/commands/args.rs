@@ -0,0 +1,438 @@
+use std::fmt;
+use std::time::Duration;
+
+use serenity::all::{ChannelId, RoleId, UserId};
+
+/// Unwraps an argument, or replies with the command's usage and returns from the handler.
+#[macro_export]
+macro_rules! try_arg {
+    ($ctx:expr, $msg:expr, $result:expr) => {
+        match $result {
+            Ok(value) => value,
+            Err(e) => {
+                $msg.usage_error($ctx, e).await;
+                return;
+            }
+        }
+    };
+}
+
+/// Command arguments split into positional values and `--flags`.
+///
+/// Values can be quoted with `"` or `'` to keep spaces, and `\` escapes the next character
+/// inside quotes. Quotes inside a word, like in `don't`, or that are never closed are kept as
+/// they are. Flags are switches written as `--name`.
+#[derive(Default, Debug)]
+pub struct Args {
+    raw: String,
+    positional: Vec<Token>,
+    flags: Vec<String>,
+    /// Where each flag starts in `raw`, so `raw_rest` can stop before them.
+    flag_starts: Vec<usize>,
+}
+
+#[derive(Debug)]
+struct Token {
+    value: String,
+    start: usize,
+}
+
+#[derive(Debug)]
+pub enum ArgError {
+    Missing(&'static str),
+    Invalid {
+        value: String,
+        expected: &'static str,
+    },
+}
+
+impl fmt::Display for ArgError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArgError::Missing(name) => write!(f, "Missing {name}"),
+            ArgError::Invalid { value, expected } => {
+                write!(f, "`{value}` isn't a valid {expected}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ArgError {}
+
+/// Whether `rest` has a `quote` that isn't escaped, closing one opened just before it.
+fn is_closed(rest: &str, quote: char) -> bool {
+    let mut chars = rest.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            c if c == quote => return true,
+            _ => {}
+        }
+    }
+
+    false
+}
+
+impl Args {
+    pub fn parse(input: &str) -> Self {
+        let mut args = Args {
+            raw: input.to_string(),
+            ..Default::default()
+        };
+
+        let mut chars = input.char_indices().peekable();
+
+        loop {
+            while chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+
+            let Some(&(start, _)) = chars.peek() else {
+                break;
+            };
+
+            let mut value = String::new();
+            let mut quote = None;
+            let mut quoted = false;
+
+            while let Some((i, c)) = chars.next() {
+                match (quote, c) {
+                    // Only a quote starting a value opens one, so `don't` stays a word
+                    (None, '"' | '\'') if i == start && is_closed(&input[i + 1..], c) => {
+                        quote = Some(c);
+                        quoted = true;
+                    }
+                    (None, c) if c.is_whitespace() => break,
+                    (Some(q), c) if c == q => quote = None,
+                    (Some(_), '\\') => {
+                        if let Some((_, escaped)) = chars.next() {
+                            value.push(escaped);
+                        }
+                    }
+                    (_, c) => value.push(c),
+                }
+            }
+
+            // A quoted "--x" is a value, not a flag
+            match value.strip_prefix("--") {
+                Some(flag) if !quoted && !flag.is_empty() => {
+                    args.flags.push(flag.to_lowercase());
+                    args.flag_starts.push(start);
+                }
+                _ => args.positional.push(Token { value, start }),
+            }
+        }
+
+        args
+    }
+
+    /// Builds arguments from already separated values, e.g. slash command options.
+    pub fn from_values(values: Vec<String>, flags: Vec<String>) -> Self {
+        let mut args = Args::default();
+
+        for value in values {
+            if !args.raw.is_empty() {
+                args.raw.push(' ');
+            }
+
+            args.positional.push(Token {
+                start: args.raw.len(),
+                value: value.clone(),
+            });
+            args.raw.push_str(&value);
+        }
+
+        args.flags = flags;
+        args
+    }
+
+    pub fn flag(&self, name: &str) -> bool {
+        self.flags.iter().any(|flag| flag == name)
+    }
+
+    /// The positional argument at `index`, converted to `T`.
+    pub fn get<T: FromArg>(&self, index: usize, name: &'static str) -> Result<T, ArgError> {
+        self.get_opt(index)?.ok_or(ArgError::Missing(name))
+    }
+
+    pub fn get_opt<T: FromArg>(&self, index: usize) -> Result<Option<T>, ArgError> {
+        self.positional
+            .get(index)
+            .map(|token| {
+                T::from_arg(&token.value).ok_or_else(|| ArgError::Invalid {
+                    value: token.value.clone(),
+                    expected: T::NAME,
+                })
+            })
+            .transpose()
+    }
+
+    /// Every positional argument from `index` on, joined by spaces. Meant for free text like
+    /// search queries.
+    pub fn rest(&self, index: usize, name: &'static str) -> Result<String, ArgError> {
+        let rest = self
+            .positional
+            .iter()
+            .skip(index)
+            .map(|token| token.value.as_str())
+            .collect::<Vec<_>>();
+
+        if rest.is_empty() {
+            Err(ArgError::Missing(name))
+        } else {
+            Ok(rest.join(" "))
+        }
+    }
+
+    /// The input exactly as typed from the positional argument at `index` on, quotes and all,
+    /// up to the first flag after it. Meant for values with their own syntax, like JSON.
+    pub fn raw_rest(&self, index: usize, name: &'static str) -> Result<&str, ArgError> {
+        let token = self.positional.get(index).ok_or(ArgError::Missing(name))?;
+
+        let end = self
+            .flag_starts
+            .iter()
+            .copied()
+            .find(|start| *start > token.start)
+            .unwrap_or(self.raw.len());
+
+        Ok(self.raw[token.start..end].trim())
+    }
+}
+
+/// A type that a single argument can be converted to.
+pub trait FromArg: Sized {
+    /// What the argument is called in error messages.
+    const NAME: &'static str;
+
+    fn from_arg(arg: &str) -> Option<Self>;
+}
+
+impl FromArg for String {
+    const NAME: &'static str = "text";
+
+    fn from_arg(arg: &str) -> Option<Self> {
+        Some(arg.to_string())
+    }
+}
+
+impl FromArg for i64 {
+    const NAME: &'static str = "number";
+
+    fn from_arg(arg: &str) -> Option<Self> {
+        arg.parse().ok()
+    }
+}
+
+impl FromArg for usize {
+    const NAME: &'static str = "positive number";
+
+    fn from_arg(arg: &str) -> Option<Self> {
+        arg.parse().ok()
+    }
+}
+
+impl FromArg for bool {
+    const NAME: &'static str = "on/off value";
+
+    fn from_arg(arg: &str) -> Option<Self> {
+        match arg.to_lowercase().as_str() {
+            "on" | "true" | "yes" | "enable" => Some(true),
+            "off" | "false" | "no" | "disable" => Some(false),
+            _ => None,
+        }
+    }
+}
+
+impl FromArg for Duration {
+    const NAME: &'static str = "time (like 90 or 1:30)";
+
+    fn from_arg(arg: &str) -> Option<Self> {
+        parse_duration(arg)
+    }
+}
+
+impl FromArg for UserId {
+    const NAME: &'static str = "user";
+
+    fn from_arg(arg: &str) -> Option<Self> {
+        let id = arg
+            .strip_prefix("<@")
+            .and_then(|id| id.strip_suffix('>'))
+            .map(|id| id.trim_start_matches('!'))
+            .unwrap_or(arg);
+
+        parse_id(id).map(UserId::new)
+    }
+}
+
+impl FromArg for ChannelId {
+    const NAME: &'static str = "channel";
+
+    fn from_arg(arg: &str) -> Option<Self> {
+        let id = arg
+            .strip_prefix("<#")
+            .and_then(|id| id.strip_suffix('>'))
+            .unwrap_or(arg);
+
+        parse_id(id).map(ChannelId::new)
+    }
+}
+
+impl FromArg for RoleId {
+    const NAME: &'static str = "role";
+
+    fn from_arg(arg: &str) -> Option<Self> {
+        let id = arg
+            .strip_prefix("<@&")
+            .and_then(|id| id.strip_suffix('>'))
+            .unwrap_or(arg);
+
+        parse_id(id).map(RoleId::new)
+    }
+}
+
+/// Discord ids are never 0, and the id types panic on it.
+fn parse_id(id: &str) -> Option<u64> {
+    id.parse().ok().filter(|id| *id != 0)
+}
+
+/// Parses `90`, `1:30` or `1:02:03` into a duration.
+pub fn parse_duration(text: &str) -> Option<Duration> {
+    let mut seconds: u64 = 0;
+
+    for (i, part) in text.split(':').enumerate() {
+        // Only the leading part may be longer than two digits, and at most hours:minutes:seconds
+        if i > 2 || part.is_empty() || (i > 0 && part.len() > 2) {
+            return None;
+        }
+
+        let value: u64 = part.parse().ok()?;
+        if i > 0 && value >= 60 {
+            return None;
+        }
+
+        seconds = seconds.checked_mul(60)?.checked_add(value)?;
+    }
+
+    Some(Duration::from_secs(seconds))
+}
+
+/// Formats a duration the way `parse_duration` reads it, like `1:30` or `1:02:03`.
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+
+    if hours > 0 {
+        format!("{hours}:{minutes:02}:{seconds:02}")
+    } else {
+        format!("{minutes}:{seconds:02}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(input: &str) -> Vec<String> {
+        Args::parse(input)
+            .positional
+            .into_iter()
+            .map(|token| token.value)
+            .collect()
+    }
+
+    #[test]
+    fn splits_on_whitespace() {
+        assert_eq!(values("  never  gonna\tgive "), ["never", "gonna", "give"]);
+        assert!(values("").is_empty());
+    }
+
+    #[test]
+    fn quotes_keep_spaces() {
+        assert_eq!(values(r#"move "a b" 'c d'"#), ["move", "a b", "c d"]);
+        assert_eq!(values(r#""say \"hi\"""#), [r#"say "hi""#]);
+    }
+
+    #[test]
+    fn apostrophes_inside_words_stay() {
+        assert_eq!(
+            Args::parse("don't stop me now").rest(0, "song").unwrap(),
+            "don't stop me now"
+        );
+        assert_eq!(values("rock 'n' roll"), ["rock", "n", "roll"]);
+    }
+
+    #[test]
+    fn unclosed_quotes_are_literal() {
+        assert_eq!(values("'tis the season"), ["'tis", "the", "season"]);
+        assert_eq!(values(r#"12" vinyl"#), [r#"12""#, "vinyl"]);
+    }
+
+    #[test]
+    fn flags_are_separate() {
+        let args = Args::parse("song --Force \"--not-a-flag\" --");
+
+        assert!(args.flag("force"));
+        assert!(!args.flag("not-a-flag"));
+        assert_eq!(args.rest(0, "song").unwrap(), "song --not-a-flag --");
+    }
+
+    #[test]
+    fn raw_rest_keeps_quotes() {
+        let args = Args::parse(r#"set key {"a": "b c"}"#);
+
+        assert_eq!(args.raw_rest(2, "value").unwrap(), r#"{"a": "b c"}"#);
+        assert!(args.raw_rest(4, "value").is_err());
+    }
+
+    #[test]
+    fn raw_rest_stops_at_flags() {
+        let args = Args::parse("bot_name hal 9000 --force");
+
+        assert_eq!(args.raw_rest(1, "value").unwrap(), "hal 9000");
+        assert!(args.flag("force"));
+
+        let args = Args::parse(r#"--force key ["--not", "a flag"]"#);
+        assert_eq!(args.raw_rest(1, "value").unwrap(), r#"["--not", "a flag"]"#);
+    }
+
+    #[test]
+    fn typed_arguments() {
+        let args = Args::parse("5 on <@!42> x");
+
+        assert_eq!(args.get::<usize>(0, "n").unwrap(), 5);
+        assert!(args.get::<bool>(1, "b").unwrap());
+        assert_eq!(args.get::<UserId>(2, "user").unwrap(), UserId::new(42));
+        assert!(matches!(
+            args.get::<usize>(3, "n"),
+            Err(ArgError::Invalid { .. })
+        ));
+        assert!(matches!(
+            args.get::<usize>(4, "n"),
+            Err(ArgError::Missing("n"))
+        ));
+        assert_eq!(args.get_opt::<usize>(4).unwrap(), None);
+    }
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("90"), Some(Duration::from_secs(90)));
+        assert_eq!(parse_duration("1:30"), Some(Duration::from_secs(90)));
+        assert_eq!(parse_duration("1:02:03"), Some(Duration::from_secs(3723)));
+        assert_eq!(parse_duration("120:00"), Some(Duration::from_secs(7200)));
+
+        for invalid in ["", "1:60", "1:5:", "1:002", "1:2:3:4", "-5", "1m"] {
+            assert_eq!(parse_duration(invalid), None, "{invalid}");
+        }
+    }
+
+    #[test]
+    fn durations_round_trip() {
+        for text in ["0:00", "1:30", "59:59", "1:02:03"] {
+            let duration = parse_duration(text).unwrap();
+            assert_eq!(format_duration(duration), text);
+        }
+    }
+}
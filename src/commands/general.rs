@@ -1,7 +1,5 @@
-use serde_json::Error;
+use serde_json::{Error, Value};
 use serenity::client::Context;
-use serenity::model::channel::Message;
-use serenity::prelude::CacheHttp;
 
 use chrono::Utc;
 use chrono_tz::Australia::Melbourne;
@@ -9,13 +7,138 @@ use chrono_tz::Australia::Melbourne;
 use std::fs;
 use std::process::Command;
 
-use crate::{say, ConfigContainer, Handler, ShardManagerContainer};
+use crate::commands::invocation::Invocation;
+use crate::commands::registry::{BotCommand, Category, CommandOption, CommandRegistry, OptionKind};
+use crate::util::config::{is_secret, value_at, ConfigError};
+use crate::util::permissions::Permission;
+use crate::{command_fn, say, try_arg, ConfigContainer, Handler, ShardManagerContainer};
+
+pub fn register(registry: &mut CommandRegistry) {
+    registry
+        .register(BotCommand {
+            name: "ping",
+            aliases: &[],
+            description: "Checks that the bot is alive",
+            usage: "",
+            options: &[],
+            category: Category::General,
+            permission: Permission::Everyone,
+            handler: command_fn!(ping),
+        })
+        .register(BotCommand {
+            name: "edontime",
+            aliases: &[],
+            description: "Shows the current time for Edon",
+            usage: "",
+            options: &[],
+            category: Category::General,
+            permission: Permission::Everyone,
+            handler: command_fn!(edon_time),
+        })
+        .register(BotCommand {
+            name: "edoncount",
+            aliases: &[],
+            description: "Shows how many times edontime has been used",
+            usage: "",
+            options: &[],
+            category: Category::General,
+            permission: Permission::Everyone,
+            handler: command_fn!(edon_time_count),
+        })
+        .register(BotCommand {
+            name: "update",
+            aliases: &[],
+            description: "Pulls the latest build and restarts the bot",
+            usage: "",
+            options: &[],
+            category: Category::General,
+            permission: Permission::Owner,
+            handler: command_fn!(update),
+        })
+        .register(BotCommand {
+            name: "update_config",
+            aliases: &[],
+            description: "Sets a config value, checking it against the config's types first",
+            usage: "[--force] <dotted.key> <value>",
+            options: &[
+                CommandOption {
+                    name: "key",
+                    description: "Config key to set, nested keys separated by dots",
+                    kind: OptionKind::String,
+                    required: true,
+                },
+                CommandOption {
+                    name: "value",
+                    description: "New value as JSON, or plain text for a string",
+                    kind: OptionKind::String,
+                    required: true,
+                },
+                CommandOption {
+                    name: "force",
+                    description: "Set it even if the config has no such key",
+                    kind: OptionKind::Flag,
+                    required: false,
+                },
+            ],
+            category: Category::General,
+            permission: Permission::Owner,
+            handler: command_fn!(update_config),
+        })
+        .register(BotCommand {
+            name: "config",
+            aliases: &[],
+            description: "Shows a config value, or undoes the last config change",
+            usage: "<get <dotted.key> | undo>",
+            options: &[
+                CommandOption {
+                    name: "action",
+                    description: "get or undo",
+                    kind: OptionKind::String,
+                    required: true,
+                },
+                CommandOption {
+                    name: "key",
+                    description: "Config key to show, nested keys separated by dots",
+                    kind: OptionKind::String,
+                    required: false,
+                },
+            ],
+            category: Category::General,
+            permission: Permission::Owner,
+            handler: command_fn!(config),
+        })
+        .register(BotCommand {
+            name: "test_parse",
+            aliases: &[],
+            description: "Logs how a JSON value parses",
+            usage: "<json value>",
+            options: &[CommandOption {
+                name: "value",
+                description: "JSON to parse",
+                kind: OptionKind::String,
+                required: true,
+            }],
+            category: Category::General,
+            permission: Permission::Owner,
+            handler: command_fn!(test_parse),
+        })
+        .register(BotCommand {
+            name: "log_config",
+            aliases: &[],
+            description: "Prints the current config to the bot's log",
+            usage: "",
+            options: &[],
+            category: Category::General,
+            permission: Permission::Owner,
+            handler: command_fn!(log_config),
+        });
+}
 
-pub async fn ping(_: &Handler, ctx: &Context, msg: &Message) {
+pub async fn ping(_: &Handler, ctx: &Context, msg: &Invocation) {
     crate::say!(ctx, msg, "Pong!");
 }
 
-pub async fn edon_time(_: &Handler, ctx: &Context, msg: &Message) {
+pub async fn edon_time(_: &Handler, ctx: &Context, msg: &Invocation) {
     let e_time = Utc::now().with_timezone(&Melbourne);
 
     crate::say!(
@@ -30,12 +153,10 @@ pub async fn edon_time(_: &Handler, ctx: &Context, msg: &Message) {
 
     let config_handler = store.get_mut::<ConfigContainer>().expect("Missing Config");
 
-    let mut new_config = config_handler.read_config().to_owned();
-    new_config.edon_count += 1;
-    let _ = config_handler.set_config(new_config);
+    let _ = config_handler.record(|config| config.edon_count += 1);
 }
 
-pub async fn edon_time_count(_: &Handler, ctx: &Context, msg: &Message) {
+pub async fn edon_time_count(_: &Handler, ctx: &Context, msg: &Invocation) {
     let store = ctx.data.read().await;
     let config_handler = store.get::<ConfigContainer>().expect("Missing Config");
 
@@ -47,82 +168,105 @@ pub async fn edon_time_count(_: &Handler, ctx: &Context, msg: &Message) {
     );
 }
 
-pub async fn is_admin(ctx: &Context, msg: &Message) -> bool {
-    let store = ctx.data.read().await;
-    store
-        .get::<ConfigContainer>()
-        .expect("Missing Config")
-        .read_config()
-        .auth_users
-        .contains(&msg.author.id)
+pub async fn test_parse(_: &Handler, ctx: &Context, msg: &Invocation) {
+    let command = try_arg!(ctx, msg, msg.args.raw_rest(0, "value"));
+    let p: Result<serde_json::Value, Error> = serde_json::from_str(command);
+
+    println!("Value {:?}", p);
 }
+pub async fn update_config(_: &Handler, ctx: &Context, msg: &Invocation) {
+    let key = try_arg!(ctx, msg, msg.args.get::<String>(0, "key"));
+    let value = try_arg!(ctx, msg, msg.args.raw_rest(1, "value"));
+    let force = msg.args.flag("force");
 
-pub async fn test_parse(_: &Handler, ctx: &Context, msg: &Message) {
-    if !is_admin(ctx, msg).await {
-        say!(ctx, msg, "Permission Denied.");
-        return;
-    };
+    // Anything that isn't JSON is taken as a string, so `prefix ?` works without quotes
+    let value = serde_json::from_str(value).unwrap_or_else(|_| Value::String(value.to_string()));
 
-    if let Some((_, command)) = msg.content.split_once(' ') {
-        let p: Result<serde_json::Value, Error> = serde_json::from_str(command);
+    let result = {
+        let mut store = ctx.data.write().await;
+        let config_handler = store.get_mut::<ConfigContainer>().expect("Missing Config");
 
-        println!("Value {:?}", p);
+        config_handler.set_path(&key, value, force)
+    };
+
+    match result {
+        Ok(change) => {
+            let old = change
+                .old
+                .map_or(String::from("(unset)"), |old| shown(&change.path, &old));
+
+            say!(
+                ctx,
+                msg,
+                "```diff\n- {}: {}\n+ {}: {}\n```",
+                change.path,
+                old,
+                change.path,
+                shown(&change.path, &change.new)
+            );
+        }
+        Err(e @ ConfigError::UnknownKey(_)) => {
+            say!(ctx, msg, "{}, use --force to set it anyway", e);
+        }
+        Err(e) => say!(ctx, msg, "{}", e),
     }
 }
-pub async fn update_config(_: &Handler, ctx: &Context, msg: &Message) {
-    if !is_admin(ctx, msg).await {
-        say!(ctx, msg, "Permission Denied.");
-        return;
-    };
 
-    if let Some((_, command)) = msg.content.split_once(' ') {
-        if let Some((key, value)) = command.split_once(' ') {
-            println!("Waiting for store lock");
-            let mut store = ctx.data.write().await;
-            println!("got store lock");
+pub async fn config(_: &Handler, ctx: &Context, msg: &Invocation) {
+    let action = try_arg!(ctx, msg, msg.args.get::<String>(0, "action"));
 
-            let config_handler = store.get_mut::<ConfigContainer>().expect("Missing Config");
+    match action.as_str() {
+        "get" => {
+            let key = try_arg!(ctx, msg, msg.args.get::<String>(1, "key"));
 
-            let mut new_state = config_handler.read_state().to_owned();
+            let value = {
+                let store = ctx.data.read().await;
+                let config_handler = store.get::<ConfigContainer>().expect("Missing Config");
 
-            if let serde_json::Value::Object(ref mut map) = new_state {
-                let p: Result<serde_json::Value, Error> = serde_json::from_str(value);
+                let keys = key.split('.').collect::<Vec<_>>();
+                value_at(config_handler.read_state(), &keys).cloned()
+            };
 
-                match p {
-                    Ok(val) => {
-                        map.insert(key.into(), val);
-                    }
-                    Err(e) => {
-                        say!(ctx, msg, "Failed with err: {:?}", e);
-                    }
-                }
+            match value {
+                Some(value) => say!(ctx, msg, "`{}` = `{}`", key, shown(&key, &value)),
+                None => say!(ctx, msg, "`{}` isn't set", key),
             }
-
-            if let Err(e) = config_handler.set_state(new_state) {
-                println!("Failed to set state: {}", e.to_string())
+        }
+        "undo" => {
+            let result = {
+                let mut store = ctx.data.write().await;
+                let config_handler = store.get_mut::<ConfigContainer>().expect("Missing Config");
+
+                config_handler.undo().map_err(|e| e.to_string())
+            };
+
+            match result {
+                Ok(true) => say!(ctx, msg, "Config rolled back to before the last change"),
+                Ok(false) => say!(ctx, msg, "No config changes to undo"),
+                Err(e) => say!(ctx, msg, "Failed to undo: {}", e),
             }
         }
+        other => say!(ctx, msg, "Unknown action `{}`, expected get or undo", other),
     }
 }
 
-pub async fn log_config(_: &Handler, ctx: &Context, msg: &Message) {
-    if !is_admin(ctx, msg).await {
-        say!(ctx, msg, "Permission Denied.");
-        return;
-    };
+/// A config value as it can be posted in chat, with API keys hidden.
+fn shown(path: &str, value: &Value) -> String {
+    if is_secret(path) {
+        String::from("(hidden)")
+    } else {
+        value.to_string()
+    }
+}
 
+pub async fn log_config(_: &Handler, ctx: &Context, _msg: &Invocation) {
     let store = ctx.data.read().await;
     let config_handler = store.get::<ConfigContainer>().expect("Missing Config");
 
     config_handler.print_state();
 }
 
-pub async fn update(_: &Handler, ctx: &Context, msg: &Message) {
-    if !is_admin(ctx, msg).await {
-        say!(ctx, msg, "Permission Denied.");
-        return;
-    };
-
+pub async fn update(_: &Handler, ctx: &Context, msg: &Invocation) {
     say!(ctx, msg, "Updating...");
 
     let path = std::env::current_exe().unwrap();
@@ -0,0 +1,151 @@
+use serenity::all::{
+    Colour, ComponentInteraction, CreateActionRow, CreateEmbed, CreateEmbedFooter, GuildId, UserId,
+};
+use serenity::client::Context;
+
+use crate::commands::invocation::Invocation;
+use crate::commands::pagination::{self, PageButton};
+use crate::commands::registry::{BotCommand, Category, CommandOption, CommandRegistry, OptionKind};
+use crate::util::guild_config::guild_settings;
+use crate::util::permissions::{permission_level, Permission};
+use crate::{command_fn, say, try_arg, Handler};
+
+const COMMANDS_PER_PAGE: usize = 8;
+
+pub fn register(registry: &mut CommandRegistry) {
+    registry.register(BotCommand {
+        name: "help",
+        aliases: &["commands"],
+        description: "Lists the commands you can use, or details for one command",
+        usage: "[command]",
+        options: &[CommandOption {
+            name: "command",
+            description: "Command to show details for",
+            kind: OptionKind::String,
+            required: false,
+        }],
+        category: Category::General,
+        permission: Permission::Everyone,
+        handler: command_fn!(help),
+    });
+}
+
+pub async fn help(handler: &Handler, ctx: &Context, msg: &Invocation) {
+    let prefix = guild_settings(ctx, msg.guild_id).await.command_prefix;
+
+    let Some(name) = try_arg!(ctx, msg, msg.args.get_opt::<String>(0)) else {
+        let (embed, components) =
+            help_page(handler, ctx, &prefix, msg.guild_id, msg.author.id, 0).await;
+        crate::check_msg(msg.send_embed_with_components(ctx, embed, components).await);
+        return;
+    };
+
+    let name = name.strip_prefix(prefix.as_str()).unwrap_or(&name);
+
+    // Commands the caller can't run are treated as if they don't exist
+    let level = permission_level(ctx, msg.guild_id, msg.author.id).await;
+    let command = match handler.commands.find(name) {
+        Some(command) if command.permission <= level => command,
+        _ => {
+            say!(
+                ctx,
+                msg,
+                "No command named `{}`, try `{}help` for a list",
+                name,
+                prefix
+            );
+            return;
+        }
+    };
+
+    let mut embed = CreateEmbed::new()
+        .colour(Colour::RED)
+        .title(usage_line(&prefix, command))
+        .description(command.description)
+        .field("Slash command", format!("`/{}`", command.name), true)
+        .field("Permission", command.permission.to_string(), true);
+
+    if !command.aliases.is_empty() {
+        let aliases = command
+            .aliases
+            .iter()
+            .map(|alias| format!("`{prefix}{alias}`"))
+            .collect::<Vec<_>>();
+
+        embed = embed.field("Aliases", aliases.join(", "), true);
+    }
+
+    crate::check_msg(msg.send_embed(ctx, embed).await);
+}
+
+pub async fn turn_page(
+    handler: &Handler,
+    ctx: &Context,
+    interaction: &ComponentInteraction,
+    button: &PageButton,
+) {
+    let prefix = guild_settings(ctx, interaction.guild_id)
+        .await
+        .command_prefix;
+    let (embed, components) = help_page(
+        handler,
+        ctx,
+        &prefix,
+        interaction.guild_id,
+        button.owner,
+        button.page,
+    )
+    .await;
+
+    pagination::update_page(ctx, interaction, button, embed, components).await;
+}
+
+async fn help_page(
+    handler: &Handler,
+    ctx: &Context,
+    prefix: &str,
+    guild_id: Option<GuildId>,
+    user_id: UserId,
+    page: usize,
+) -> (CreateEmbed, Vec<CreateActionRow>) {
+    let level = permission_level(ctx, guild_id, user_id).await;
+    let visible = handler
+        .commands
+        .iter()
+        .filter(|command| command.permission <= level)
+        .collect::<Vec<_>>();
+
+    let pages = pagination::page_count(visible.len(), COMMANDS_PER_PAGE);
+    let page = page.min(pages - 1);
+
+    let mut embed = CreateEmbed::new()
+        .colour(Colour::RED)
+        .title("Commands")
+        .footer(CreateEmbedFooter::new(format!(
+            "Page {}/{} - {}help <command> for details",
+            page + 1,
+            pages,
+            prefix
+        )));
+
+    for command in visible
+        .iter()
+        .skip(page * COMMANDS_PER_PAGE)
+        .take(COMMANDS_PER_PAGE)
+    {
+        embed = embed.field(usage_line(prefix, command), command.description, false);
+    }
+
+    (
+        embed,
+        pagination::page_buttons("help", user_id, page, pages),
+    )
+}
+
+fn usage_line(prefix: &str, command: &BotCommand) -> String {
+    if command.usage.is_empty() {
+        format!("{}{}", prefix, command.name)
+    } else {
+        format!("{}{} {}", prefix, command.name, command.usage)
+    }
+}
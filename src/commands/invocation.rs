@@ -0,0 +1,206 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use serenity::all::{
+    Cache, ChannelId, CommandDataOptionValue, CommandInteraction, CreateActionRow, CreateEmbed,
+    CreateInteractionResponseFollowup, CreateMessage, EditInteractionResponse, GuildId, GuildRef,
+    RoleId, User,
+};
+use serenity::client::Context;
+use serenity::model::channel::Message;
+use serenity::prelude::CacheHttp;
+use serenity::Result as SerenityResult;
+
+use super::args::{ArgError, Args};
+use super::registry::{BotCommand, OptionKind};
+
+enum Source {
+    Message(Box<Message>),
+    Interaction(Box<CommandInteraction>),
+}
+
+/// A single use of a command, either from a prefixed chat message or a slash command.
+///
+/// Handlers only talk to the caller through this, so the same handler serves both paths.
+pub struct Invocation {
+    pub author: User,
+    pub guild_id: Option<GuildId>,
+    pub channel_id: ChannelId,
+    pub args: Args,
+    prefix: String,
+    command_name: &'static str,
+    usage: &'static str,
+    source: Source,
+    responded: AtomicBool,
+}
+
+impl Invocation {
+    pub fn from_message(msg: Message, prefix: &str, command: &BotCommand, args: &str) -> Self {
+        Self {
+            author: msg.author.clone(),
+            guild_id: msg.guild_id,
+            channel_id: msg.channel_id,
+            args: Args::parse(args),
+            prefix: prefix.to_string(),
+            command_name: command.name,
+            usage: command.usage,
+            source: Source::Message(Box::new(msg)),
+            responded: AtomicBool::new(false),
+        }
+    }
+
+    pub fn from_interaction(interaction: CommandInteraction, command: &BotCommand) -> Self {
+        // Values go in declared option order, so handlers read them like prefix arguments
+        let mut values = Vec::new();
+        let mut flags = Vec::new();
+
+        for option in command.options {
+            let Some(given) = interaction
+                .data
+                .options
+                .iter()
+                .find(|given| given.name == option.name)
+            else {
+                continue;
+            };
+
+            let value = match &given.value {
+                CommandDataOptionValue::Boolean(set) => {
+                    if option.kind == OptionKind::Flag && *set {
+                        flags.push(option.name.to_string());
+                    }
+                    continue;
+                }
+                CommandDataOptionValue::String(s) => s.clone(),
+                CommandDataOptionValue::Integer(i) => i.to_string(),
+                CommandDataOptionValue::Number(n) => n.to_string(),
+                CommandDataOptionValue::User(id) => format!("<@{id}>"),
+                CommandDataOptionValue::Channel(id) => format!("<#{id}>"),
+                CommandDataOptionValue::Role(id) => format!("<@&{id}>"),
+                CommandDataOptionValue::Mentionable(id) => {
+                    let role_id = RoleId::new(id.get());
+                    if interaction.data.resolved.roles.contains_key(&role_id) {
+                        format!("<@&{id}>")
+                    } else {
+                        format!("<@{id}>")
+                    }
+                }
+                _ => continue,
+            };
+
+            values.push(value);
+        }
+
+        Self {
+            author: interaction.user.clone(),
+            guild_id: interaction.guild_id,
+            channel_id: interaction.channel_id,
+            args: Args::from_values(values, flags),
+            prefix: String::from("/"),
+            command_name: command.name,
+            usage: command.usage,
+            source: Source::Interaction(Box::new(interaction)),
+            responded: AtomicBool::new(false),
+        }
+    }
+
+    pub fn guild<'a>(&self, cache: &'a Cache) -> Option<GuildRef<'a>> {
+        cache.guild(self.guild_id?)
+    }
+
+    /// The chat message that triggered this, if it wasn't a slash command.
+    pub fn message(&self) -> Option<&Message> {
+        match &self.source {
+            Source::Message(msg) => Some(msg),
+            Source::Interaction(_) => None,
+        }
+    }
+
+    /// Replies with what was wrong with the arguments and how the command is used.
+    pub async fn usage_error(&self, ctx: &Context, error: ArgError) {
+        let usage = if self.usage.is_empty() {
+            format!("{}{}", self.prefix, self.command_name)
+        } else {
+            format!("{}{} {}", self.prefix, self.command_name, self.usage)
+        };
+
+        crate::check_msg(self.say(ctx, format!("{error}. Usage: `{usage}`")).await);
+    }
+
+    pub async fn say(&self, ctx: &Context, content: impl Into<String>) -> SerenityResult<Message> {
+        self.respond(ctx, Some(content.into()), None, Vec::new())
+            .await
+    }
+
+    pub async fn send_embed(&self, ctx: &Context, embed: CreateEmbed) -> SerenityResult<Message> {
+        self.respond(ctx, None, Some(embed), Vec::new()).await
+    }
+
+    pub async fn send_embed_with_components(
+        &self,
+        ctx: &Context,
+        embed: CreateEmbed,
+        components: Vec<CreateActionRow>,
+    ) -> SerenityResult<Message> {
+        self.respond(ctx, None, Some(embed), components).await
+    }
+
+    /// Slash commands are deferred while the handler runs; make sure one that never replied
+    /// doesn't sit on "thinking..." forever.
+    pub async fn finish(&self, ctx: &Context) {
+        if let Source::Interaction(_) = self.source {
+            if !self.responded.load(Ordering::SeqCst) {
+                crate::check_msg(self.say(ctx, "Done.").await);
+            }
+        }
+    }
+
+    async fn respond(
+        &self,
+        ctx: &Context,
+        content: Option<String>,
+        embed: Option<CreateEmbed>,
+        components: Vec<CreateActionRow>,
+    ) -> SerenityResult<Message> {
+        match &self.source {
+            Source::Message(_) => {
+                let mut builder = CreateMessage::new();
+                if let Some(content) = content {
+                    builder = builder.content(content);
+                }
+                if let Some(embed) = embed {
+                    builder = builder.embed(embed);
+                }
+                builder = builder.components(components);
+
+                self.channel_id.send_message(ctx.http(), builder).await
+            }
+
+            // The first reply fills in the deferred response, anything after is a followup.
+            Source::Interaction(interaction) => {
+                if !self.responded.swap(true, Ordering::SeqCst) {
+                    let mut builder = EditInteractionResponse::new();
+                    if let Some(content) = content {
+                        builder = builder.content(content);
+                    }
+                    if let Some(embed) = embed {
+                        builder = builder.embed(embed);
+                    }
+                    builder = builder.components(components);
+
+                    interaction.edit_response(ctx.http(), builder).await
+                } else {
+                    let mut builder = CreateInteractionResponseFollowup::new();
+                    if let Some(content) = content {
+                        builder = builder.content(content);
+                    }
+                    if let Some(embed) = embed {
+                        builder = builder.embed(embed);
+                    }
+                    builder = builder.components(components);
+
+                    interaction.create_followup(ctx.http(), builder).await
+                }
+            }
+        }
+    }
+}
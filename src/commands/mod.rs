@@ -1,4 +1,13 @@
+pub mod args;
 pub mod general;
+pub mod help;
+pub mod invocation;
 pub mod music;
 pub mod music_util;
+pub mod pagination;
+pub mod permissions;
+pub mod playback;
+pub mod queue;
+pub mod registry;
+pub mod settings;
 pub mod ytdl;
@@ -2,73 +2,356 @@ use std::sync::Arc;
 use std::time::Duration;
 
 use serde::Deserialize;
-use serenity::all::ChannelId;
-use serenity::all::{Colour, CreateEmbed, CreateEmbedAuthor, CreateMessage};
+use serenity::all::{
+    ChannelId, Colour, ComponentInteraction, CreateActionRow, CreateEmbed, CreateEmbedFooter,
+    GuildId, UserId,
+};
 use serenity::client::Context;
-use serenity::model::channel::Message;
-use serenity::prelude::CacheHttp;
 
-use songbird::error::JoinResult;
 use songbird::input::{AudioStreamError, AuxMetadata, Compose};
+use songbird::tracks::TrackHandle;
 use songbird::Call;
 
-use songbird::CoreEvent;
 use songbird::Event;
+use songbird::TrackEvent;
 
 use tokio::sync::Mutex;
 use tokio::time::Instant;
 
-use crate::{say, Handler, HttpKey, TrackMetaKey};
-
+use crate::commands::args::format_duration;
+use crate::commands::invocation::Invocation;
+use crate::commands::pagination::{self, PageButton};
+use crate::commands::registry::{BotCommand, Category, CommandOption, CommandRegistry, OptionKind};
+use crate::util::guild_config::{guild_settings, GuildSettings};
+use crate::util::permissions::{is_explicit_dj, Permission};
+use crate::util::playback::{loop_mode, playback_states, LoopMode};
+use crate::util::stage::{show_track_as_topic, take_stage, StageTopicHandler};
+use crate::{command_fn, say, try_arg, Handler, HttpKey};
+
+use super::music_util::{
+    enqueue, listeners, set_track_metadata, track_embed, track_info, HistoryHandler, LoopHandler,
+    TrackInfo,
+};
 use super::ytdl::{self, Ytdl};
 
-pub async fn join(ctx: &Context, msg: &Message) -> JoinResult<Arc<tokio::sync::Mutex<Call>>> {
-    let (guild_id, channel_id) = {
-        let guild = msg.guild(&ctx.cache).unwrap();
+const QUEUE_PAGE_LENGTH: usize = 10;
+
+pub fn register(registry: &mut CommandRegistry) {
+    registry
+        .register(BotCommand {
+            name: "play",
+            aliases: &["p"],
+            description: "Plays a song or playlist from a URL or search query",
+            usage: "[--next] <url or search query>",
+            options: &[
+                CommandOption {
+                    name: "query",
+                    description: "URL or search query",
+                    kind: OptionKind::String,
+                    required: true,
+                },
+                CommandOption {
+                    name: "next",
+                    description: "Play it after the current song instead of at the end",
+                    kind: OptionKind::Flag,
+                    required: false,
+                },
+            ],
+            category: Category::Queue,
+            permission: Permission::Everyone,
+            handler: command_fn!(play),
+        })
+        .register(BotCommand {
+            name: "playnext",
+            aliases: &["pn"],
+            description: "Plays a song right after the current one",
+            usage: "<url or search query>",
+            options: &[CommandOption {
+                name: "query",
+                description: "URL or search query",
+                kind: OptionKind::String,
+                required: true,
+            }],
+            category: Category::Queue,
+            permission: Permission::Everyone,
+            handler: command_fn!(play_next),
+        })
+        .register(BotCommand {
+            name: "stop",
+            aliases: &[],
+            description: "Stops playback and clears the queue",
+            usage: "",
+            options: &[],
+            category: Category::Queue,
+            permission: Permission::Dj,
+            handler: command_fn!(stop),
+        })
+        .register(BotCommand {
+            name: "pause",
+            aliases: &["resume"],
+            description: "Pauses or resumes the current song",
+            usage: "",
+            options: &[],
+            category: Category::Music,
+            permission: Permission::Everyone,
+            handler: command_fn!(pause),
+        })
+        .register(BotCommand {
+            name: "join",
+            aliases: &[],
+            description: "Joins your voice channel",
+            usage: "",
+            options: &[],
+            category: Category::Music,
+            permission: Permission::Everyone,
+            handler: |_, ctx, msg| {
+                Box::pin(async move {
+                    let _ = join(ctx, msg).await;
+                })
+            },
+        })
+        .register(BotCommand {
+            name: "leave",
+            aliases: &["disconnect", "dc"],
+            description: "Stops playback and leaves the voice channel",
+            usage: "",
+            options: &[],
+            category: Category::Queue,
+            permission: Permission::Dj,
+            handler: command_fn!(leave),
+        })
+        .register(BotCommand {
+            name: "movebot",
+            aliases: &["summon"],
+            description: "Moves the bot to your voice channel, or the one given",
+            usage: "[channel]",
+            options: &[CommandOption {
+                name: "channel",
+                description: "Voice channel to move to, yours if left out",
+                kind: OptionKind::Channel,
+                required: false,
+            }],
+            category: Category::Queue,
+            permission: Permission::Dj,
+            handler: command_fn!(move_bot),
+        })
+        .register(BotCommand {
+            name: "queue",
+            aliases: &["q"],
+            description: "Shows the upcoming songs",
+            usage: "[page]",
+            options: &[CommandOption {
+                name: "page",
+                description: "Page of the queue to show",
+                kind: OptionKind::Integer,
+                required: false,
+            }],
+            category: Category::Music,
+            permission: Permission::Everyone,
+            handler: command_fn!(queue),
+        })
+        .register(BotCommand {
+            name: "skip",
+            aliases: &[],
+            description: "Votes to skip the current song, or skips it if you queued it or are a DJ",
+            usage: "",
+            options: &[],
+            category: Category::Queue,
+            permission: Permission::Everyone,
+            handler: command_fn!(skip),
+        })
+        .register(BotCommand {
+            name: "yt_test",
+            aliases: &[],
+            description: "Times a YouTube API search",
+            usage: "<search query>",
+            options: &[CommandOption {
+                name: "query",
+                description: "Search query",
+                kind: OptionKind::String,
+                required: true,
+            }],
+            category: Category::Music,
+            permission: Permission::Everyone,
+            handler: command_fn!(yt_test),
+        });
+}
 
-        let channel_id = guild
-            .voice_states
-            .get(&msg.author.id)
-            .and_then(|voice_state| voice_state.channel_id);
+/// Gets the bot into the caller's voice channel. Only DJs can pull it away from a channel where
+/// people are still listening, everyone else is asked to join that channel instead.
+pub async fn join(ctx: &Context, msg: &Invocation) -> Option<Arc<Mutex<Call>>> {
+    let Some(guild_id) = msg.guild_id else {
+        say!(ctx, msg, "Voice only works in a server");
+        return None;
+    };
 
-        (guild.id, channel_id)
+    let Some(author_channel) = author_voice_channel(ctx, msg) else {
+        say!(ctx, msg, "Please join a voice channel");
+        return None;
     };
 
-    let connect_to = match channel_id {
-        Some(channel) => channel,
-        None => {
-            say!(ctx, msg, "Please join a voice channel");
-            return JoinResult::Err(songbird::error::JoinError::NoCall);
+    let manager = songbird::get(ctx)
+        .await
+        .expect("Songbird Voice client placed in at initialisation.");
+
+    if let Some(call) = manager.get(guild_id) {
+        let current = call.lock().await.current_channel();
+        if current.is_some_and(|current| ChannelId::from(current.0) == author_channel) {
+            return Some(call);
         }
-    };
+    }
+
+    if taken_by_listeners(ctx, msg, guild_id).await {
+        return None;
+    }
+
+    connect(ctx, msg, guild_id, author_channel).await
+}
 
-    let manager = songbird::get(&ctx)
+/// Joins `channel_id`, or moves there if already in another channel of the guild.
+async fn connect(
+    ctx: &Context,
+    msg: &Invocation,
+    guild_id: GuildId,
+    channel_id: ChannelId,
+) -> Option<Arc<Mutex<Call>>> {
+    let manager = songbird::get(ctx)
         .await
         .expect("Songbird Voice client placed in at initialisation.");
 
-    println!("awating join");
-    let res = manager.join(guild_id, connect_to).await;
-    println!("joined");
-
-    match &res {
-        Ok(v) => {
-            v.lock().await.add_global_event(
-                Event::Core(CoreEvent::ClientDisconnect),
-                crate::commands::music_util::UserDisconnectHandler {
-                    call: v.clone(),
-                    cache: ctx.cache.clone(),
-                },
-            );
-            println!("Registered leave event");
+    match manager.join(guild_id, channel_id).await {
+        Ok(call) => {
+            add_call_events(ctx, guild_id, &call).await;
+
+            if let Some(reason) = take_stage(&ctx.cache, &ctx.http, guild_id, channel_id).await {
+                say!(ctx, msg, "{}", reason);
+            }
+
+            Some(call)
         }
-        
         Err(e) => {
             println!("Failed to join channel : {e}");
             say!(ctx, msg, "Error lacking permissions for that channel");
+            None
         }
     }
+}
+
+/// Whether the bot is playing to people elsewhere, who only a DJ can take it away from. Tells
+/// the author where to go instead if so.
+async fn taken_by_listeners(ctx: &Context, msg: &Invocation, guild_id: GuildId) -> bool {
+    let Some(call) = get_songbird(ctx, msg).await else {
+        return false;
+    };
+    let Some(current) = call.lock().await.current_channel() else {
+        return false;
+    };
+    let current = ChannelId::from(current.0);
+
+    let occupied =
+        listeners(&ctx.cache, guild_id, current).is_some_and(|listeners| !listeners.is_empty());
+
+    // Everyone counts as a DJ while nobody has been given it, which would let anyone through
+    if !occupied || is_explicit_dj(ctx, Some(guild_id), msg.author.id).await {
+        return false;
+    }
+
+    say!(
+        ctx,
+        msg,
+        "I'm playing in <#{}>, join that channel instead",
+        current
+    );
+    true
+}
+
+fn author_voice_channel(ctx: &Context, msg: &Invocation) -> Option<ChannelId> {
+    msg.guild(&ctx.cache)?
+        .voice_states
+        .get(&msg.author.id)?
+        .channel_id
+}
+
+pub async fn leave(_handler: &Handler, ctx: &Context, msg: &Invocation) {
+    let manager = songbird::get(ctx)
+        .await
+        .expect("Songbird Voice client placed in at initialisation.");
+
+    let Some((guild_id, call)) = msg
+        .guild_id
+        .and_then(|guild_id| Some((guild_id, manager.get(guild_id)?)))
+    else {
+        say!(ctx, msg, "I'm not in a voice channel");
+        return;
+    };
+
+    call.lock().await.queue().stop();
+
+    match manager.remove(guild_id).await {
+        Ok(()) => say!(ctx, msg, "Left the voice channel"),
+        Err(e) => say!(ctx, msg, "Failed to leave: {}", e),
+    }
+}
+
+/// Moves the bot to `channel`, or to the caller's voice channel.
+pub async fn move_bot(_handler: &Handler, ctx: &Context, msg: &Invocation) {
+    let Some(guild_id) = msg.guild_id else {
+        say!(ctx, msg, "Voice only works in a server");
+        return;
+    };
+
+    let channel = try_arg!(ctx, msg, msg.args.get_opt::<ChannelId>(0));
+
+    let Some(channel) = channel.or_else(|| author_voice_channel(ctx, msg)) else {
+        say!(ctx, msg, "Join a voice channel, or name the one to move to");
+        return;
+    };
+
+    if taken_by_listeners(ctx, msg, guild_id).await {
+        return;
+    }
+
+    if connect(ctx, msg, guild_id, channel).await.is_some() {
+        say!(ctx, msg, "Moved to <#{}>", channel);
+    }
+}
+
+/// Sets up the handlers every call needs, after joining a channel.
+pub async fn add_call_events(ctx: &Context, guild_id: GuildId, call: &Arc<Mutex<Call>>) {
+    let playback = playback_states(ctx).await;
+    let mut call_unlocked = call.lock().await;
+
+    // Joining again returns the same call, don't stack up a second set of handlers
+    call_unlocked.remove_all_global_events();
+
+    for event in [TrackEvent::Play, TrackEvent::End] {
+        call_unlocked.add_global_event(
+            Event::Track(event),
+            LoopHandler {
+                call: call.clone(),
+                guild_id,
+                playback: playback.clone(),
+            },
+        );
+    }
 
-    return res;
+    call_unlocked.add_global_event(
+        Event::Track(TrackEvent::End),
+        HistoryHandler {
+            guild_id,
+            playback: playback.clone(),
+        },
+    );
+
+    call_unlocked.add_global_event(
+        Event::Track(TrackEvent::Play),
+        StageTopicHandler {
+            call: call.clone(),
+            guild_id,
+            cache: ctx.cache.clone(),
+            http: ctx.http.clone(),
+        },
+    );
 }
 
 fn debug_time(instant: &mut Instant, string: &str) {
@@ -80,101 +363,124 @@ fn debug_time(instant: &mut Instant, string: &str) {
     *instant = Instant::now();
 }
 
-pub async fn play_playlist(_: &Handler, ctx: &Context, msg: &Message) {
-    let author_channel_id = {
-        let guild = msg.guild(&ctx.cache).unwrap();
-
-        let channel_id: Option<ChannelId> = guild
-            .voice_states
-            .get(&msg.author.id)
-            .and_then(|voice_state| voice_state.channel_id);
-
-        channel_id
-    };
-
-    let (_, http_client) = {
+pub async fn play_playlist(
+    _: &Handler,
+    ctx: &Context,
+    msg: &Invocation,
+    url: &str,
+    play_next: bool,
+) {
+    let http_client = {
         let data = ctx.data.read().await;
 
-        (
-            data.get::<crate::ConfigContainer>()
-                .expect("Config")
-                .read_config()
-                .clone(),
-            data.get::<HttpKey>()
-                .cloned()
-                .expect("Guaranteed to exist in the typemap."),
-        )
+        data.get::<HttpKey>()
+            .cloned()
+            .expect("Guaranteed to exist in the typemap.")
     };
+    let settings = guild_settings(ctx, msg.guild_id).await;
+
+    if let Ok(mut song_list) = ytdl::query_playlist(url, http_client).await {
+        let Some(call_mutex) = join(ctx, msg).await else {
+            return;
+        };
+        let mut call = call_mutex.lock().await;
 
-    if let Some((_, song_to_play)) = msg.content.split_once(' ') {
-        if let Ok(mut song_list) = ytdl::query_playlist(song_to_play, http_client).await {
-            let call_mutex = get_call(ctx, msg).await;
-            let mut call = call_mutex.lock().await;
+        let mut tracks = Vec::new();
+        let mut too_long = 0;
+        for track in &mut song_list[..] {
+            let metadata = track.aux_metadata().await.unwrap();
 
-            if !(call.current_channel().map(|i| i.0.get()) == author_channel_id.map(|i| i.get())) {
-                println!("switching channel");
-                let _ = call.join(author_channel_id.unwrap()).await;
-                println!("done");
+            if exceeds_length(&settings, &metadata).is_some() {
+                too_long += 1;
+                continue;
             }
 
-            println!("Added {} to the playlist", song_list.len());
-
-            let mut first_meta: Option<AuxMetadata> = None;
+            tracks.push((track.clone(), metadata));
+        }
 
-            for track in &mut song_list[..] {
-                let track_handle =
-                    call.enqueue_with_preload(track.clone().into(), Some(Duration::from_secs(1)));
+        if too_long > 0 {
+            say!(
+                ctx,
+                msg,
+                "Skipping {} songs longer than {}",
+                too_long,
+                settings
+                    .max_track_length
+                    .map_or(String::new(), format_duration)
+            );
+        }
 
-                let metadata = track.aux_metadata().await.unwrap();
+        let mut space = queue_space(&settings, &call.queue().current_queue(), msg.author.id).await;
+        if let Some(max) = settings.max_playlist_length {
+            if space.as_ref().is_none_or(|(space, _)| max < *space) {
+                space = Some((max, format!("playlists are limited to {max} songs")));
+            }
+        }
 
-                if first_meta == None {
-                    first_meta = Some(metadata.clone())
-                }
+        if let Some((space, limit)) = space {
+            if space == 0 {
+                say!(ctx, msg, "Can't queue any more songs, {}", limit);
+                return;
+            }
 
-                track_handle
-                    .typemap()
-                    .write()
-                    .await
-                    .insert::<TrackMetaKey>(metadata.clone());
+            if tracks.len() > space {
+                say!(
+                    ctx,
+                    msg,
+                    "Only queuing the first {} songs, {}",
+                    space,
+                    limit
+                );
+                tracks.truncate(space);
             }
+        }
+
+        println!("Added {} to the playlist", tracks.len());
+
+        for (track, metadata) in &tracks {
+            let track_handle = enqueue(
+                &mut call,
+                TrackInfo::new(msg, url, track.clone()),
+                volume_scale(settings.default_volume),
+            )
+            .await;
+
+            set_track_metadata(&track_handle, metadata.clone()).await;
+        }
 
-            if let Some(metadata) = first_meta {
-                let mut embed =
-                    CreateEmbed::new()
-                        .colour(Colour::RED)
-                        .author(CreateEmbedAuthor::new(format!(
-                            "Queuing {} from Playlist",
-                            song_list.len()
-                        )))
-                        .title(metadata.title.as_ref().unwrap_or(&String::from("Unknown")))
-                        .url(metadata.source_url.as_ref().unwrap_or(&String::from(
-                            "https://www.youtube.com/watch?v=dQw4w9WgXcQ",
-                        )));
-
-                let blank = String::new();
-                if let Some((_, video_id)) = metadata
-                    .source_url
-                    .as_ref()
-                    .unwrap_or(&blank)
-                    .split_once("?v=")
-                {
-                    embed = embed
-                        .thumbnail(format!("https://i3.ytimg.com/vi/{video_id}/hqdefault.jpg"));
+        // Move them from the back of the queue to right after the current song, in order
+        let added = tracks.len();
+        if play_next && call.queue().len() > added + 1 {
+            call.queue().modify_queue(|queue| {
+                let queued = queue.split_off(queue.len() - added);
+                for (i, track) in queued.into_iter().enumerate() {
+                    queue.insert(1 + i, track);
                 }
+            });
+        }
 
-                let _ = msg
-                    .channel_id
-                    .send_message(ctx.http(), CreateMessage::new().add_embed(embed))
-                    .await;
-            }
+        if let Some((_, metadata)) = tracks.first() {
+            let title = if play_next {
+                format!("Playing {added} from Playlist Next")
+            } else {
+                format!("Queuing {added} from Playlist")
+            };
+            let embed = track_embed(title, metadata);
+
+            let _ = msg.send_embed(ctx, embed).await;
         }
     }
 }
 
 async fn get_info_from_embed(
     ctx: &Context,
-    msg: &Message,
+    msg: &Invocation,
 ) -> Result<AuxMetadata, AudioStreamError> {
+    // Slash commands have no message for Discord to embed
+    let Some(msg) = msg.message() else {
+        return Err(AudioStreamError::Unsupported);
+    };
+
     loop {
         let new = msg.channel_id.message(ctx, msg.id).await;
         if let Ok(new_message) = new {
@@ -194,93 +500,79 @@ async fn get_info_from_embed(
     }
 }
 
-async fn get_call<'a>(ctx: &'a Context, msg: &'a Message) -> Arc<Mutex<Call>> {
-    let songbird = songbird::get(&ctx)
-        .await
-        .expect("Songbird Voice client placed in at initialisation.");
+pub async fn yt_test(_: &Handler, ctx: &Context, msg: &Invocation) {
+    let start = Instant::now();
 
-    let call_handler = match songbird.get(msg.guild_id.unwrap()) {
-        Some(unlocked) => unlocked,
-        None => {
-            println!("Could not find songbird");
+    let song_to_play = try_arg!(ctx, msg, msg.args.rest(0, "search query"));
 
-            let t = join(ctx, msg).await.expect("Failed to join call!");
-            t
-        }
-    };
+    let (config, http_client) = {
+        let data = ctx.data.read().await;
 
-    return call_handler;
-}
+        (
+            data.get::<crate::ConfigContainer>()
+                .expect("Config")
+                .read_config()
+                .clone(),
+            data.get::<HttpKey>()
+                .cloned()
+                .expect("Guaranteed to exist in the typemap."),
+        )
+    };
 
-pub async fn yt_test(_: &Handler, ctx: &Context, msg: &Message) {
-    let start = Instant::now();
+    let res = http_client.get(format!("https://www.googleapis.com/youtube/v3/search?part=snippet&q={}&key={}&fields=items(id(videoId),snippet(title,thumbnails(high(url))))&maxResults=1", song_to_play, config.yt_api_key)).send().await.unwrap();
 
-    if let Some((_, song_to_play)) = msg.content.split_once(' ') {
-        let (config, http_client) = {
-            let data = ctx.data.read().await;
-
-            (
-                data.get::<crate::ConfigContainer>()
-                    .expect("Config")
-                    .read_config()
-                    .clone(),
-                data.get::<HttpKey>()
-                    .cloned()
-                    .expect("Guaranteed to exist in the typemap."),
-            )
-        };
+    println!("Status {}", res.status());
 
-        let res = http_client.get(format!("https://www.googleapis.com/youtube/v3/search?part=snippet&q={}&key={}&fields=items(id(videoId),snippet(title,thumbnails(high(url))))&maxResults=1", song_to_play.to_string(), config.yt_api_key)).send().await.unwrap();
+    let item = res.json::<YTApiResponse>().await.unwrap();
 
-        println!("Status {}", res.status());
+    let end = Instant::now();
+    say!(
+        ctx,
+        msg,
+        "yt reponded with {} in {}ms",
+        item.items.first().unwrap().title,
+        end.duration_since(start).as_millis()
+    );
+}
 
-        let item = res.json::<YTApiResponse>().await.unwrap();
+pub async fn play(handler: &Handler, ctx: &Context, msg: &Invocation) {
+    play_query(handler, ctx, msg, msg.args.flag("next")).await;
+}
 
-        let end = Instant::now();
-        say!(
-            ctx,
-            msg,
-            "yt reponded with {} in {}ms",
-            item.items.first().unwrap().title,
-            end.duration_since(start).as_millis()
-        );
-    }
+pub async fn play_next(handler: &Handler, ctx: &Context, msg: &Invocation) {
+    play_query(handler, ctx, msg, true).await;
 }
 
-pub async fn play(handler: &Handler, ctx: &Context, msg: &Message) {
+async fn play_query(handler: &Handler, ctx: &Context, msg: &Invocation, play_next: bool) {
     let mut timer = Instant::now();
 
-    let author_channel_id = {
-        let guild = msg.guild(&ctx.cache).unwrap();
-
-        let channel_id: Option<ChannelId> = guild
-            .voice_states
-            .get(&msg.author.id)
-            .and_then(|voice_state| voice_state.channel_id);
-
-        channel_id
-    };
-
-    let Some((_, song_to_play)) = msg.content.split_once(' ') else {
-        say!(ctx, msg, "No song specified");
-        return;
-    };
+    let song_to_play = try_arg!(ctx, msg, msg.args.rest(0, "song"));
+    let song_to_play = song_to_play.as_str();
 
     if song_to_play.contains("&list=") {
         println!("playing playlist");
-        play_playlist(handler, ctx, msg).await;
+        play_playlist(handler, ctx, msg, song_to_play, play_next).await;
         return;
     }
 
     debug_time(&mut timer, "starting");
 
-    let call_mutex = get_call(ctx, msg).await;
+    let Some(call_mutex) = join(ctx, msg).await else {
+        return;
+    };
     let mut call = call_mutex.lock().await;
 
-    // Auto disconnect stuff here!
-
     debug_time(&mut timer, "getting call");
 
+    let settings = guild_settings(ctx, msg.guild_id).await;
+
+    if let Some((0, limit)) =
+        queue_space(&settings, &call.queue().current_queue(), msg.author.id).await
+    {
+        say!(ctx, msg, "Can't queue any more songs, {}", limit);
+        return;
+    }
+
     /*
     if song_to_play.contains("www.youtube.com") {
         if let Ok(Some(embed)) =
@@ -309,17 +601,20 @@ pub async fn play(handler: &Handler, ctx: &Context, msg: &Message) {
 
     debug_time(&mut timer, "pl check");
 
-    let mut track: Ytdl = match song_to_play.starts_with("https") || song_to_play.starts_with("www.") {
+    let mut track: Ytdl = match song_to_play.starts_with("https")
+        || song_to_play.starts_with("www.")
+    {
         true => Ytdl::new(http_client, song_to_play.to_string()),
         false => {
-            if call.queue().len() != 0 {
+            // The API's results have no duration, so a length limit needs the slow search
+            if !call.queue().is_empty() || settings.max_track_length.is_some() {
                 println!("using slow search track");
                 Ytdl::new_search(http_client, song_to_play.to_string())
             } else {
                 println!("using fast search track");
                 debug_time(&mut timer, "making yt req");
 
-                let res = http_client.get(format!("https://www.googleapis.com/youtube/v3/search?part=snippet&q={}&key={}&fields=items(id(videoId),snippet(title,thumbnails(high(url))))&maxResults=1", song_to_play.to_string(), config.yt_api_key)).send().await.unwrap();
+                let res = http_client.get(format!("https://www.googleapis.com/youtube/v3/search?part=snippet&q={}&key={}&fields=items(id(videoId),snippet(title,thumbnails(high(url))))&maxResults=1", song_to_play, config.yt_api_key)).send().await.unwrap();
                 let txt = res.text().await.unwrap();
                 let item = serde_json::from_str::<YTApiResponse>(&txt);
 
@@ -339,7 +634,6 @@ pub async fn play(handler: &Handler, ctx: &Context, msg: &Message) {
                             ..Default::default()
                         };
 
-
                         Ytdl::new_custom_meta(
                             Some(meta),
                             http_client,
@@ -360,101 +654,107 @@ pub async fn play(handler: &Handler, ctx: &Context, msg: &Message) {
 
     debug_time(&mut timer, "getting track");
 
-    if !(call.current_channel().map(|i| i.0.get()) == author_channel_id.map(|i| i.get())) {
-        println!("switching channel");
-        let _ = call.join(author_channel_id.unwrap()).await;
-        println!("done");
-    }
+    // Waiting on the metadata holds playback up, so only do it first when the length matters
+    let checked_metadata = match settings.max_track_length {
+        Some(_) => Some(query_metadata(ctx, msg, &mut track).await),
+        None => None,
+    };
 
-    debug_time(&mut timer, "joining call");
+    if let Some(metadata) = &checked_metadata {
+        if let Some(max) = exceeds_length(&settings, metadata) {
+            say!(
+                ctx,
+                msg,
+                "That song is {} long, songs can be at most {}",
+                metadata.duration.map_or(String::new(), format_duration),
+                format_duration(max)
+            );
+            return;
+        }
+    }
 
-    let yt_track: songbird::tracks::Track = track.clone().into();
-    let track_handle = call.enqueue_with_preload(yt_track, Some(Duration::from_secs(1)));
+    let track_handle = enqueue(
+        &mut call,
+        TrackInfo::new(msg, song_to_play, track.clone()),
+        volume_scale(settings.default_volume),
+    )
+    .await;
+
+    // Move it from the back of the queue to right after the current song
+    if play_next && call.queue().len() > 2 {
+        call.queue().modify_queue(|queue| {
+            if let Some(queued) = queue.pop_back() {
+                queue.insert(1, queued);
+            }
+        });
+    }
 
     debug_time(&mut timer, "enqueue with preload");
 
-    //let metadata = track.aux_metadata().await.unwrap();
-
-    let time = tokio::time::Duration::from_secs(10);
-
-    let metadata = tokio::select! {
-        Ok(Ok(aux_meta)) = tokio::time::timeout(time, track.aux_metadata()) => {
-            debug_time(&mut timer, "getting metadata from aux metadata");
-            aux_meta
-        },
-        Ok(Ok(embed_meta)) = tokio::time::timeout(time, get_info_from_embed(ctx, msg)) => {
-            debug_time(&mut timer, "getting metadata from aux metadata");
-            embed_meta
-        },
-        else => {
-            debug_time(&mut timer, "Failed to metta data");
-            AuxMetadata::default()
-        },
+    let metadata = match checked_metadata {
+        Some(metadata) => metadata,
+        None => query_metadata(ctx, msg, &mut track).await,
     };
 
     debug_time(&mut timer, "getting metadata");
 
-    track_handle
-        .typemap()
-        .write()
-        .await
-        .insert::<TrackMetaKey>(metadata.clone());
+    set_track_metadata(&track_handle, metadata.clone()).await;
+
+    // A search that started playing straight away had only the query for its Stage topic
+    let playing = call
+        .queue()
+        .current()
+        .is_some_and(|current| current.uuid() == track_handle.uuid());
+    if let (Some(guild_id), Some(channel)) = (msg.guild_id, call.current_channel()) {
+        if playing {
+            let channel_id = ChannelId::from(channel.0);
+            show_track_as_topic(&ctx.cache, &ctx.http, guild_id, channel_id, &track_handle).await;
+        }
+    }
 
     debug_time(&mut timer, "getting track handle");
 
-    let title_text = if call.queue().len() == 1 {
+    let now_playing = call.queue().len() == 1;
+    let title_text = if now_playing {
         "Now Playing".to_string()
+    } else if play_next {
+        "Playing Next".to_string()
     } else {
         "Queuing".to_string()
     };
 
-    let mut embed = CreateEmbed::new()
-        .colour(Colour::RED)
-        .author(CreateEmbedAuthor::new(title_text))
-        .title(
-            metadata
-                .title
-                .as_ref()
-                .unwrap_or(&String::from("Failed to grab title")),
-        )
-        .url(
-            metadata
-                .source_url
-                .as_ref()
-                .unwrap_or(&String::from("https://www.youtube.com/watch?v=dQw4w9WgXcQ")),
-        );
-
-    let blank = String::new();
+    let mut embed = track_embed(title_text, &metadata);
 
-    debug_time(&mut timer, "3");
-
-    if let Some((_, video_id)) = metadata
-        .source_url
-        .as_ref()
-        .unwrap_or(&blank)
-        .split_once("?v=")
-    {
-        embed = embed.thumbnail(format!("https://i3.ytimg.com/vi/{video_id}/hqdefault.jpg"));
+    if now_playing {
+        embed = with_loop_mode(embed, loop_mode(ctx, msg.guild_id).await);
     }
 
-    let _ = msg
-        .channel_id
-        .send_message(ctx.http(), CreateMessage::new().add_embed(embed))
-        .await;
+    let _ = msg.send_embed(ctx, embed).await;
 
     debug_time(&mut timer, "sending embed");
 }
 
-pub async fn pause(_handler: &Handler, ctx: &Context, msg: &Message) {
-    let songbird = songbird::get(&ctx)
+/// The track's metadata, or the message embed's if that turns up first. Gives up after a while
+/// with empty metadata rather than holding the command up.
+async fn query_metadata(ctx: &Context, msg: &Invocation, track: &mut Ytdl) -> AuxMetadata {
+    let time = tokio::time::Duration::from_secs(10);
+
+    tokio::select! {
+        Ok(Ok(aux_meta)) = tokio::time::timeout(time, track.aux_metadata()) => aux_meta,
+        Ok(Ok(embed_meta)) = tokio::time::timeout(time, get_info_from_embed(ctx, msg)) => embed_meta,
+        else => {
+            println!("Failed to get metadata");
+            AuxMetadata::default()
+        },
+    }
+}
+
+pub async fn pause(_handler: &Handler, ctx: &Context, msg: &Invocation) {
+    let songbird = songbird::get(ctx)
         .await
         .expect("Songbird Voice client placed in at initialisation.");
 
-    match msg
-        .guild_id
-        .map(|guild_id| songbird.get(guild_id))
-        .flatten()
-    {
+    match msg.guild_id.and_then(|guild_id| songbird.get(guild_id)) {
         Some(call_handler) => {
             let call_handler = call_handler.lock().await;
 
@@ -485,99 +785,265 @@ pub async fn pause(_handler: &Handler, ctx: &Context, msg: &Message) {
     }
 }
 
-pub async fn stop(_handler: &Handler, ctx: &Context, msg: &Message) {
-    let songbird = songbird::get(&ctx)
-        .await
-        .expect("Songbird Voice client placed in at initialisation.");
-
-    match songbird.get(msg.guild_id.unwrap()) {
+pub async fn stop(_handler: &Handler, ctx: &Context, msg: &Invocation) {
+    match get_songbird(ctx, msg).await {
         Some(call_handler) => {
             let call_handler = call_handler.lock().await;
             call_handler.queue().stop();
             say!(ctx, msg, "Stopping");
         }
 
-        None => {
-            println!("songbird.get failed!")
-        }
+        None => say!(ctx, msg, "Nothing is playing"),
     }
 }
 
-pub async fn skip(_handler: &Handler, ctx: &Context, msg: &Message) {
-    if let Some(call_handler) = get_songbird(ctx, msg).await {
-        let call_handler = call_handler.lock().await;
+pub async fn skip(_handler: &Handler, ctx: &Context, msg: &Invocation) {
+    let (Some(guild_id), Some(call)) = (msg.guild_id, get_songbird(ctx, msg).await) else {
+        say!(ctx, msg, "Nothing is playing");
+        return;
+    };
+    let call = call.lock().await;
 
-        let _ = call_handler.queue().skip();
+    let Some(current) = call.queue().current() else {
+        say!(ctx, msg, "Nothing is playing");
+        return;
+    };
+
+    // Whoever queued the song, and DJs, don't need anyone to agree
+    let requester = track_info(&current).await.map(|info| info.requester);
+    if requester == Some(msg.author.id) || is_explicit_dj(ctx, Some(guild_id), msg.author.id).await
+    {
+        let _ = call.queue().skip();
+        say!(ctx, msg, "Skipped");
+        return;
+    }
+
+    let listeners = call
+        .current_channel()
+        .and_then(|channel| listeners(&ctx.cache, guild_id, ChannelId::new(channel.0.get())))
+        .unwrap_or_default();
+
+    if !listeners.contains(&msg.author.id) {
+        say!(ctx, msg, "Join the voice channel to vote to skip");
+        return;
+    }
+
+    let percent = guild_settings(ctx, Some(guild_id)).await.skip_vote_percent as usize;
+    let needed = (listeners.len() * percent).div_ceil(100).max(1);
+
+    let votes = {
+        let playback = playback_states(ctx).await;
+        let mut playback = playback.lock().await;
+        let skip_votes = &mut playback.entry(guild_id).or_default().skip_votes;
+
+        skip_votes.vote(&current, msg.author.id);
+
+        // Only people still listening count
+        skip_votes
+            .voters
+            .iter()
+            .filter(|voter| listeners.contains(voter))
+            .count()
+    };
+
+    if votes >= needed {
+        let _ = call.queue().skip();
+        say!(ctx, msg, "Skipped ({}/{} votes)", votes, needed);
+    } else {
+        say!(ctx, msg, "Voted to skip ({}/{} votes)", votes, needed);
     }
 }
 
-pub async fn queue(_handler: &Handler, ctx: &Context, msg: &Message) {
-    if let Some(call_handler) = get_songbird(ctx, msg).await {
-        let call_handler = call_handler.lock().await;
-        let mut i: u32 = 0;
+pub async fn queue(_handler: &Handler, ctx: &Context, msg: &Invocation) {
+    let page = try_arg!(ctx, msg, msg.args.get_opt::<usize>(0))
+        .unwrap_or(1)
+        .max(1);
+
+    let Some(guild_id) = msg.guild_id else {
+        say!(ctx, msg, "Queue is empty");
+        return;
+    };
+
+    let (embed, components) = queue_page(ctx, guild_id, msg.author.id, page - 1).await;
+    crate::check_msg(msg.send_embed_with_components(ctx, embed, components).await);
+}
 
-        let current_queue = call_handler.queue().current_queue();
+pub async fn turn_queue_page(
+    ctx: &Context,
+    interaction: &ComponentInteraction,
+    button: &PageButton,
+) {
+    let Some(guild_id) = interaction.guild_id else {
+        return;
+    };
 
-        if current_queue.is_empty() {
-            let embed = CreateEmbed::new()
-                .colour(Colour::RED)
-                .title(String::from("Queue is empty"));
+    let (embed, components) = queue_page(ctx, guild_id, button.owner, button.page).await;
+    pagination::update_page(ctx, interaction, button, embed, components).await;
+}
 
-            let _ = msg
-                .channel_id
-                .send_message(ctx.http(), CreateMessage::new().add_embed(embed))
-                .await;
+/// One page of the queue, with when each song should start playing.
+async fn queue_page(
+    ctx: &Context,
+    guild_id: GuildId,
+    user_id: UserId,
+    page: usize,
+) -> (CreateEmbed, Vec<CreateActionRow>) {
+    let songbird = songbird::get(ctx)
+        .await
+        .expect("Songbird Voice client placed in at initialisation.");
 
-            return;
+    let tracks = match songbird.get(guild_id) {
+        Some(call) => call.lock().await.queue().current_queue(),
+        None => Vec::new(),
+    };
+
+    let Some((current, upcoming)) = tracks.split_first() else {
+        let embed = CreateEmbed::new()
+            .colour(Colour::RED)
+            .title(String::from("Queue is empty"));
+
+        return (embed, Vec::new());
+    };
+
+    let info = track_info(current).await;
+    let duration = info.as_ref().and_then(|info| info.metadata.duration);
+    let position = current
+        .get_info()
+        .await
+        .map(|state| state.position)
+        .unwrap_or_default();
+
+    // Stays known only while every song before has a known length
+    let mut until_next = duration.map(|duration| duration.saturating_sub(position));
+
+    let mut embed = CreateEmbed::new().colour(Colour::RED).title("Queue").field(
+        "Now Playing",
+        format!(
+            "{} `{} / {}`{}",
+            info.as_ref().map_or("Unknown", |info| info.title()),
+            format_duration(position),
+            duration.map_or(String::from("?"), format_duration),
+            requested_by(info.as_ref())
+        ),
+        false,
+    );
+
+    let pages = pagination::page_count(upcoming.len(), QUEUE_PAGE_LENGTH);
+    let page = page.min(pages - 1);
+    let first = page * QUEUE_PAGE_LENGTH;
+
+    let mut lines = Vec::new();
+    for (i, track) in upcoming.iter().enumerate() {
+        let info = track_info(track).await;
+        let duration = info.as_ref().and_then(|info| info.metadata.duration);
+
+        if (first..first + QUEUE_PAGE_LENGTH).contains(&i) {
+            lines.push(format!(
+                "`{}.` {} `{}` - plays in {}{}",
+                i + 1,
+                info.as_ref().map_or("Unknown", |info| info.title()),
+                duration.map_or(String::from("?"), format_duration),
+                until_next.map_or(String::from("?"), format_duration),
+                requested_by(info.as_ref())
+            ));
         }
 
-        for track in current_queue.iter().take(5) {
-            if let Some(metadata) = track.typemap().read().await.get::<TrackMetaKey>() {
-                let title_text = if i == 0 {
-                    "Now Playing".to_string()
-                } else {
-                    format!("#{} in Queue", i)
-                };
-
-                let mut embed =
-                    CreateEmbed::new()
-                        .colour(Colour::RED)
-                        .author(CreateEmbedAuthor::new(title_text))
-                        .title(metadata.title.as_ref().unwrap_or(&String::from("Unknown")))
-                        .url(metadata.source_url.as_ref().unwrap_or(&String::from(
-                            "https://www.youtube.com/watch?v=dQw4w9WgXcQ",
-                        )));
-
-                let blank = String::new();
-
-                if let Some((_, video_id)) = metadata
-                    .source_url
-                    .as_ref()
-                    .unwrap_or(&blank)
-                    .split_once("?v=")
-                {
-                    embed = embed
-                        .thumbnail(format!("https://i3.ytimg.com/vi/{video_id}/hqdefault.jpg"));
-                }
+        until_next = until_next.zip(duration).map(|(a, b)| a + b);
+    }
+
+    // Ten lines can outgrow a field, the description holds much more
+    if !lines.is_empty() {
+        embed = embed.description(format!("**Up Next**\n{}", lines.join("\n")));
+    }
+
+    let remaining = until_next.map_or(String::from("unknown"), format_duration);
+    let footer = format!(
+        "Page {}/{} - {} songs, {} remaining",
+        page + 1,
+        pages,
+        tracks.len(),
+        remaining
+    );
+    let footer = match loop_mode(ctx, Some(guild_id)).await {
+        LoopMode::Off => footer,
+        mode => format!("{footer} - Loop: {mode}"),
+    };
+    embed = embed.footer(CreateEmbedFooter::new(footer));
+
+    (
+        embed,
+        pagination::page_buttons("queue", user_id, page, pages),
+    )
+}
 
-                let _ = msg
-                    .channel_id
-                    .send_message(ctx.http(), CreateMessage::new().add_embed(embed))
-                    .await;
-                i += 1;
+/// How many more songs `user_id` can queue, and the limit that stops them, or nothing if there's
+/// no limit.
+async fn queue_space(
+    settings: &GuildSettings,
+    queue: &[TrackHandle],
+    user_id: UserId,
+) -> Option<(usize, String)> {
+    let mut space = settings.max_queue_length.map(|max| {
+        (
+            max.saturating_sub(queue.len()),
+            format!("the queue is limited to {max} songs"),
+        )
+    });
+
+    if let Some(max) = settings.max_tracks_per_user {
+        let mut queued = 0;
+        for track in queue {
+            if track_info(track)
+                .await
+                .is_some_and(|info| info.requester == user_id)
+            {
+                queued += 1;
             }
         }
+
+        let user_space = max.saturating_sub(queued);
+        if space.as_ref().is_none_or(|(space, _)| user_space < *space) {
+            space = Some((
+                user_space,
+                format!("everyone is limited to {max} songs in the queue"),
+            ));
+        }
+    }
+
+    space
+}
+
+/// The length limit a song is over, if any. Songs of unknown length are let through.
+fn exceeds_length(settings: &GuildSettings, metadata: &AuxMetadata) -> Option<Duration> {
+    settings
+        .max_track_length
+        .filter(|max| metadata.duration.is_some_and(|duration| duration > *max))
+}
+
+fn requested_by(info: Option<&TrackInfo>) -> String {
+    info.map_or(String::new(), |info| format!(" - <@{}>", info.requester))
+}
+
+/// Notes the loop mode on a now playing embed, unless looping is off.
+pub fn with_loop_mode(embed: CreateEmbed, mode: LoopMode) -> CreateEmbed {
+    if mode == LoopMode::Off {
+        embed
+    } else {
+        embed.footer(CreateEmbedFooter::new(format!("Loop: {mode}")))
     }
 }
 
-async fn get_songbird(ctx: &Context, msg: &Message) -> Option<Arc<Mutex<Call>>> {
-    let songbird = songbird::get(&ctx)
+/// Songbird volume for a percentage, where 1.0 is unchanged.
+pub fn volume_scale(percent: u32) -> f32 {
+    percent as f32 / 100.0
+}
+
+pub async fn get_songbird(ctx: &Context, msg: &Invocation) -> Option<Arc<Mutex<Call>>> {
+    let songbird = songbird::get(ctx)
         .await
         .expect("Songbird Voice client placed in at initialisation.");
 
-    msg.guild_id
-        .map(|guild_id| songbird.get(guild_id))
-        .flatten()
+    msg.guild_id.and_then(|guild_id| songbird.get(guild_id))
 }
 
 #[derive(Deserialize, Debug)]
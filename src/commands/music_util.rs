@@ -1,47 +1,210 @@
-use serenity::all::{Cache, ChannelId};
+use serenity::all::{
+    Cache, ChannelId, Colour, CreateEmbed, CreateEmbedAuthor, GuildId, Timestamp, UserId,
+};
 use serenity::async_trait;
+use songbird::input::AuxMetadata;
+use songbird::tracks::{PlayMode, TrackHandle};
 use songbird::{Call, Event, EventContext, EventHandler};
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::Mutex;
 
-pub struct UserDisconnectHandler {
+use super::invocation::Invocation;
+use super::ytdl::Ytdl;
+use crate::util::playback::{LoopMode, PlaybackStates, HISTORY_LENGTH};
+use crate::TrackMetaKey;
+
+/// Everything known about a queued track, kept in its typemap under [`TrackMetaKey`].
+#[derive(Clone, Debug)]
+pub struct TrackInfo {
+    /// Default until `play` has looked the track up.
+    pub metadata: AuxMetadata,
+    pub requester: UserId,
+    pub requested_at: Timestamp,
+    /// What was asked for, a URL or search terms.
+    pub query: String,
+    /// Text channel the request came from.
+    pub channel_id: ChannelId,
+    /// Played again by loop modes, so it has to point at the exact video once known.
+    pub source: Ytdl,
+}
+
+impl TrackInfo {
+    pub fn new(msg: &Invocation, query: impl Into<String>, source: Ytdl) -> Self {
+        Self {
+            metadata: AuxMetadata::default(),
+            requester: msg.author.id,
+            requested_at: Timestamp::now(),
+            query: query.into(),
+            channel_id: msg.channel_id,
+            source,
+        }
+    }
+
+    /// The track's title, or what was searched for until it's known.
+    pub fn title(&self) -> &str {
+        self.metadata.title.as_deref().unwrap_or(&self.query)
+    }
+
+    /// Like "<@id> <t:…:R>", which Discord shows as the requester and how long ago they asked.
+    pub fn requested_by(&self) -> String {
+        format!(
+            "<@{}> <t:{}:R>",
+            self.requester,
+            self.requested_at.unix_timestamp()
+        )
+    }
+}
+
+/// Adds a track to the end of the queue at `volume` (1.0 is unchanged).
+pub async fn enqueue(call: &mut Call, info: TrackInfo, volume: f32) -> TrackHandle {
+    let handle =
+        call.enqueue_with_preload(info.source.clone().into(), Some(Duration::from_secs(1)));
+    let _ = handle.set_volume(volume);
+    handle.typemap().write().await.insert::<TrackMetaKey>(info);
+
+    handle
+}
+
+/// Records what a track turned out to be, so loops replay this exact video instead of searching
+/// again.
+pub async fn set_track_metadata(track: &TrackHandle, metadata: AuxMetadata) {
+    if let Some(info) = track.typemap().write().await.get_mut::<TrackMetaKey>() {
+        info.source = info.source.resolved(metadata.clone());
+        info.metadata = metadata;
+    }
+}
+
+/// The embed music commands reply with for a single track.
+pub fn track_embed(author: impl Into<String>, metadata: &AuxMetadata) -> CreateEmbed {
+    let mut embed = CreateEmbed::new()
+        .colour(Colour::RED)
+        .author(CreateEmbedAuthor::new(author))
+        .title(metadata.title.as_deref().unwrap_or("Unknown"))
+        .url(
+            metadata
+                .source_url
+                .as_deref()
+                .unwrap_or("https://www.youtube.com/watch?v=dQw4w9WgXcQ"),
+        );
+
+    if let Some((_, video_id)) = metadata
+        .source_url
+        .as_deref()
+        .unwrap_or_default()
+        .split_once("?v=")
+    {
+        embed = embed.thumbnail(format!("https://i3.ytimg.com/vi/{video_id}/hqdefault.jpg"));
+    }
+
+    embed
+}
+
+pub async fn track_info(track: &TrackHandle) -> Option<TrackInfo> {
+    track.typemap().read().await.get::<TrackMetaKey>().cloned()
+}
+
+/// What `play` found out about a track, or nothing if it's still being looked up.
+pub async fn track_metadata(track: &TrackHandle) -> AuxMetadata {
+    track
+        .typemap()
+        .read()
+        .await
+        .get::<TrackMetaKey>()
+        .map(|info| info.metadata.clone())
+        .unwrap_or_default()
+}
+
+/// The people, not bots, in a voice channel.
+pub fn listeners(cache: &Cache, guild_id: GuildId, channel_id: ChannelId) -> Option<Vec<UserId>> {
+    let guild = cache.guild(guild_id)?;
+    let guild_channel = guild.channels.get(&channel_id)?;
+    let members = guild_channel.members(cache).ok()?;
+
+    Some(
+        members
+            .iter()
+            .filter(|member| !member.user.bot)
+            .map(|member| member.user.id)
+            .collect(),
+    )
+}
+
+/// Applies a guild's [`LoopMode`] as tracks start and end.
+pub struct LoopHandler {
     pub call: Arc<Mutex<Call>>,
-    pub cache: Arc<Cache>,
+    pub guild_id: GuildId,
+    pub playback: PlaybackStates,
 }
 
 #[async_trait]
-impl EventHandler for UserDisconnectHandler {
+impl EventHandler for LoopHandler {
     async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
-        match ctx {
-            EventContext::ClientDisconnect(_) => {
-                // Wait a few seconds to leave (and to check if we should)
-                let _ = tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
-
-                let mut call_unlocked = self.call.lock().await;
-                let c = call_unlocked.current_connection();
-
-                let shoud_leave = match c {
-                    Some(details) => {
-                        let guild = self.cache.guild(details.guild_id.0)?;
-                        let guild_channel = guild
-                            .channels
-                            .get(&ChannelId::new(details.channel_id?.0.into()))?;
-                        let members = guild_channel.members(&self.cache).ok()?;
-
-                        !members.iter().any(|mem| !mem.user.bot)
-                    }
-                    _ => false,
-                };
-
-                println!("Should leave: {:?}", shoud_leave);
-
-                if shoud_leave {
-                    let _ = call_unlocked.leave().await;
-                };
+        let EventContext::Track(tracks) = ctx else {
+            return None;
+        };
+
+        let mode = self
+            .playback
+            .lock()
+            .await
+            .get(&self.guild_id)
+            .map(|playback| playback.loop_mode)
+            .unwrap_or_default();
+
+        for (state, track) in tracks.iter() {
+            match (&state.playing, mode) {
+                (PlayMode::Play, LoopMode::Track) => {
+                    let _ = track.enable_loop();
+                }
+                // Only tracks that played to the end, skipped or removed ones stay gone
+                (PlayMode::End, LoopMode::Queue) => {
+                    let Some(info) = track_info(track).await else {
+                        continue;
+                    };
+
+                    // Enqueuing creates the stream again, the old URL may have expired
+                    let mut call = self.call.lock().await;
+                    enqueue(&mut call, info, state.volume).await;
+                }
+                _ => {}
             }
-            _ => {}
+        }
+
+        None
+    }
+}
+
+/// Remembers finished tracks for `history`.
+pub struct HistoryHandler {
+    pub guild_id: GuildId,
+    pub playback: PlaybackStates,
+}
+
+#[async_trait]
+impl EventHandler for HistoryHandler {
+    async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
+        let EventContext::Track(tracks) = ctx else {
+            return None;
         };
 
+        for (state, track) in tracks.iter() {
+            // Stopping the queue ends every queued track too, only keep ones that were heard
+            if state.playing != PlayMode::End && state.play_time.is_zero() {
+                continue;
+            }
+
+            let Some(info) = track_info(track).await else {
+                continue;
+            };
+
+            let mut playback = self.playback.lock().await;
+            let history = &mut playback.entry(self.guild_id).or_default().history;
+
+            history.push_front(info);
+            history.truncate(HISTORY_LENGTH);
+        }
+
         None
     }
 }
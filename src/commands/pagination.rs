@@ -0,0 +1,76 @@
+use serenity::all::{
+    ButtonStyle, ComponentInteraction, CreateActionRow, CreateButton, CreateEmbed,
+    CreateInteractionResponse, CreateInteractionResponseMessage, UserId,
+};
+use serenity::client::Context;
+
+/// Previous/next buttons for a paged embed.
+///
+/// The page state lives entirely in the button ids (`<kind>:<owner>:<page>`), so turning a page
+/// works without remembering anything and keeps working across restarts.
+pub struct PageButton {
+    pub kind: String,
+    pub owner: UserId,
+    pub page: usize,
+}
+
+impl PageButton {
+    pub fn parse(custom_id: &str) -> Option<Self> {
+        let mut parts = custom_id.splitn(3, ':');
+
+        Some(Self {
+            kind: parts.next()?.to_string(),
+            owner: UserId::new(parts.next()?.parse().ok()?),
+            page: parts.next()?.parse().ok()?,
+        })
+    }
+}
+
+pub fn page_count(items: usize, per_page: usize) -> usize {
+    items.div_ceil(per_page).max(1)
+}
+
+/// Buttons to move from `page` to its neighbours, or nothing if everything fits on one page.
+pub fn page_buttons(kind: &str, owner: UserId, page: usize, pages: usize) -> Vec<CreateActionRow> {
+    if pages <= 1 {
+        return Vec::new();
+    }
+
+    vec![CreateActionRow::Buttons(vec![
+        CreateButton::new(format!("{kind}:{owner}:{}", page.saturating_sub(1)))
+            .label("Previous")
+            .style(ButtonStyle::Secondary)
+            .disabled(page == 0),
+        CreateButton::new(format!("{kind}:{owner}:{}", page + 1))
+            .label("Next")
+            .style(ButtonStyle::Secondary)
+            .disabled(page + 1 >= pages),
+    ])]
+}
+
+/// Replaces the paged message with a new page, or tells anyone but its owner to ask for their own.
+pub async fn update_page(
+    ctx: &Context,
+    interaction: &ComponentInteraction,
+    button: &PageButton,
+    embed: CreateEmbed,
+    components: Vec<CreateActionRow>,
+) {
+    let response = if interaction.user.id == button.owner {
+        CreateInteractionResponse::UpdateMessage(
+            CreateInteractionResponseMessage::new()
+                .embed(embed)
+                .components(components),
+        )
+    } else {
+        CreateInteractionResponse::Message(
+            CreateInteractionResponseMessage::new()
+                .content("Only the person who asked can turn these pages.")
+                .ephemeral(true),
+        )
+    };
+
+    if let Err(e) = interaction.create_response(&ctx.http, response).await {
+        println!("Failed to turn page: {e}");
+    }
+}
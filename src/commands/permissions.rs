@@ -0,0 +1,175 @@
+use serenity::all::{Colour, CreateEmbed, GuildId};
+use serenity::client::Context;
+
+use crate::commands::invocation::Invocation;
+use crate::commands::registry::{BotCommand, Category, CommandOption, CommandRegistry, OptionKind};
+use crate::util::permissions::{permission_level, Grantee, Permission};
+use crate::{command_fn, say, try_arg, ConfigContainer, Handler};
+
+pub fn register(registry: &mut CommandRegistry) {
+    registry
+        .register(BotCommand {
+            name: "grant",
+            aliases: &[],
+            description: "Gives a user or role a permission level in this server",
+            usage: "<everyone|dj|admin> <@user|@role>",
+            options: &[
+                CommandOption {
+                    name: "level",
+                    description: "everyone, dj or admin",
+                    kind: OptionKind::String,
+                    required: true,
+                },
+                CommandOption {
+                    name: "target",
+                    description: "User or role to grant it to",
+                    kind: OptionKind::Mentionable,
+                    required: true,
+                },
+            ],
+            category: Category::General,
+            permission: Permission::Admin,
+            handler: command_fn!(grant),
+        })
+        .register(BotCommand {
+            name: "revoke",
+            aliases: &[],
+            description: "Removes a user's or role's permission level in this server",
+            usage: "<@user|@role>",
+            options: &[CommandOption {
+                name: "target",
+                description: "User or role to revoke",
+                kind: OptionKind::Mentionable,
+                required: true,
+            }],
+            category: Category::General,
+            permission: Permission::Admin,
+            handler: command_fn!(revoke),
+        })
+        .register(BotCommand {
+            name: "permissions",
+            aliases: &["perms"],
+            description: "Shows your permission level and everything granted in this server",
+            usage: "",
+            options: &[],
+            category: Category::General,
+            permission: Permission::Everyone,
+            handler: command_fn!(permissions),
+        });
+}
+
+pub async fn grant(_: &Handler, ctx: &Context, msg: &Invocation) {
+    let level = try_arg!(ctx, msg, msg.args.get::<Permission>(0, "permission level"));
+    let grantee = try_arg!(ctx, msg, msg.args.get::<Grantee>(1, "user or role"));
+
+    let Some(guild_id) = msg.guild_id else {
+        say!(ctx, msg, "Permissions can only be granted in a server");
+        return;
+    };
+
+    let own_level = permission_level(ctx, Some(guild_id), msg.author.id).await;
+    if level > own_level {
+        say!(
+            ctx,
+            msg,
+            "You can't grant more than your own level ({})",
+            own_level
+        );
+        return;
+    }
+
+    match update_grants(ctx, guild_id, grantee, Some(level)).await {
+        Ok(_) => say!(ctx, msg, "Granted {} to {}", level, grantee),
+        Err(e) => say!(ctx, msg, "Failed to save permissions: {}", e),
+    }
+}
+
+pub async fn revoke(_: &Handler, ctx: &Context, msg: &Invocation) {
+    let grantee = try_arg!(ctx, msg, msg.args.get::<Grantee>(0, "user or role"));
+
+    let Some(guild_id) = msg.guild_id else {
+        say!(ctx, msg, "Permissions can only be revoked in a server");
+        return;
+    };
+
+    match update_grants(ctx, guild_id, grantee, None).await {
+        Ok(Some(level)) => say!(ctx, msg, "Revoked {} from {}", level, grantee),
+        Ok(None) => say!(ctx, msg, "{} had nothing granted", grantee),
+        Err(e) => say!(ctx, msg, "Failed to save permissions: {}", e),
+    }
+}
+
+pub async fn permissions(_: &Handler, ctx: &Context, msg: &Invocation) {
+    let level = permission_level(ctx, msg.guild_id, msg.author.id).await;
+
+    let guild_config = {
+        let store = ctx.data.read().await;
+        let config = store
+            .get::<ConfigContainer>()
+            .expect("Missing Config")
+            .read_config();
+
+        msg.guild_id
+            .and_then(|guild_id| config.guilds.get(&guild_id).cloned())
+            .unwrap_or_default()
+    };
+
+    let mut grants = guild_config
+        .user_permissions
+        .iter()
+        .map(|(id, level)| (*level, Grantee::User(*id)))
+        .chain(
+            guild_config
+                .role_permissions
+                .iter()
+                .map(|(id, level)| (*level, Grantee::Role(*id))),
+        )
+        .collect::<Vec<_>>();
+
+    grants.sort_by_key(|(level, _)| std::cmp::Reverse(*level));
+
+    let mut lines = grants
+        .iter()
+        .map(|(level, grantee)| format!("{grantee}: {level}"))
+        .collect::<Vec<_>>();
+
+    if let Some(dj_role) = guild_config.dj_role {
+        lines.push(format!("{}: DJ (dj_role)", Grantee::Role(dj_role)));
+    }
+
+    if !guild_config.grants_dj() {
+        lines.push(String::from("Nobody is granted DJ, so everyone is a DJ"));
+    }
+
+    let embed = CreateEmbed::new()
+        .colour(Colour::RED)
+        .title(format!("Your permission level: {level}"))
+        .field("Granted in this server", lines.join("\n"), false);
+
+    crate::check_msg(msg.send_embed(ctx, embed).await);
+}
+
+/// Sets or removes a grant, returning what was granted before.
+async fn update_grants(
+    ctx: &Context,
+    guild_id: GuildId,
+    grantee: Grantee,
+    level: Option<Permission>,
+) -> Result<Option<Permission>, String> {
+    let mut store = ctx.data.write().await;
+    let config_handler = store.get_mut::<ConfigContainer>().expect("Missing Config");
+
+    let mut previous = None;
+    config_handler
+        .update_guild(guild_id, |guild| {
+            previous = match (grantee, level) {
+                (Grantee::User(id), Some(level)) => guild.user_permissions.insert(id, level),
+                (Grantee::User(id), None) => guild.user_permissions.remove(&id),
+                (Grantee::Role(id), Some(level)) => guild.role_permissions.insert(id, level),
+                (Grantee::Role(id), None) => guild.role_permissions.remove(&id),
+            };
+        })
+        .map_err(|e| e.to_string())?;
+
+    Ok(previous)
+}
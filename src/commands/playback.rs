@@ -0,0 +1,346 @@
+use serenity::all::{CreateEmbed, EditMessage, GuildId};
+use serenity::client::Context;
+use songbird::tracks::TrackHandle;
+use std::time::Duration;
+
+use crate::commands::args::format_duration;
+use crate::commands::invocation::Invocation;
+use crate::commands::music::{get_songbird, volume_scale, with_loop_mode};
+use crate::commands::music_util::{track_embed, track_info, track_metadata};
+use crate::commands::registry::{BotCommand, Category, CommandOption, CommandRegistry, OptionKind};
+use crate::util::guild_config::guild_settings;
+use crate::util::permissions::Permission;
+use crate::util::playback::{loop_mode, playback_states};
+use crate::{command_fn, say, try_arg, ConfigContainer, Handler};
+
+/// How far `ff` and `rewind` jump when not told.
+const DEFAULT_JUMP: Duration = Duration::from_secs(10);
+
+/// How often a `nowplaying` message is edited to move its progress bar along.
+const NOW_PLAYING_REFRESH: Duration = Duration::from_secs(5);
+
+/// Width of the progress bar, in segments.
+const PROGRESS_BAR_LENGTH: usize = 20;
+
+pub fn register(registry: &mut CommandRegistry) {
+    registry
+        .register(BotCommand {
+            name: "seek",
+            aliases: &[],
+            description: "Jumps to a time in the current song",
+            usage: "<time>",
+            options: &[CommandOption {
+                name: "time",
+                description: "Where to jump to, like 90 or 1:30",
+                kind: OptionKind::String,
+                required: true,
+            }],
+            category: Category::Music,
+            permission: Permission::Dj,
+            handler: command_fn!(seek),
+        })
+        .register(BotCommand {
+            name: "ff",
+            aliases: &["forward"],
+            description: "Skips ahead in the current song",
+            usage: "[time]",
+            options: &[CommandOption {
+                name: "time",
+                description: "How far to skip, like 30 or 1:30 (10 seconds if left out)",
+                kind: OptionKind::String,
+                required: false,
+            }],
+            category: Category::Music,
+            permission: Permission::Dj,
+            handler: command_fn!(fast_forward),
+        })
+        .register(BotCommand {
+            name: "rewind",
+            aliases: &["rw"],
+            description: "Goes back in the current song",
+            usage: "[time]",
+            options: &[CommandOption {
+                name: "time",
+                description: "How far to go back, like 15 or 1:30 (10 seconds if left out)",
+                kind: OptionKind::String,
+                required: false,
+            }],
+            category: Category::Music,
+            permission: Permission::Dj,
+            handler: command_fn!(rewind),
+        })
+        .register(BotCommand {
+            name: "nowplaying",
+            aliases: &["np"],
+            description: "Shows the current song and how far through it is",
+            usage: "",
+            options: &[],
+            category: Category::Music,
+            permission: Permission::Everyone,
+            handler: command_fn!(now_playing),
+        })
+        .register(BotCommand {
+            name: "volume",
+            aliases: &["vol"],
+            description: "Shows or changes the volume for this server",
+            usage: "[0-200]",
+            options: &[CommandOption {
+                name: "volume",
+                description: "Volume in percent, 100 is unchanged",
+                kind: OptionKind::Integer,
+                required: false,
+            }],
+            category: Category::Music,
+            permission: Permission::Dj,
+            handler: command_fn!(volume),
+        });
+}
+
+pub async fn seek(_: &Handler, ctx: &Context, msg: &Invocation) {
+    let position = try_arg!(ctx, msg, msg.args.get::<Duration>(0, "time"));
+
+    seek_with(ctx, msg, |_| Some(position)).await;
+}
+
+pub async fn fast_forward(_: &Handler, ctx: &Context, msg: &Invocation) {
+    let jump = try_arg!(ctx, msg, msg.args.get_opt::<Duration>(0)).unwrap_or(DEFAULT_JUMP);
+
+    seek_with(ctx, msg, |position| position.checked_add(jump)).await;
+}
+
+pub async fn rewind(_: &Handler, ctx: &Context, msg: &Invocation) {
+    let jump = try_arg!(ctx, msg, msg.args.get_opt::<Duration>(0)).unwrap_or(DEFAULT_JUMP);
+
+    seek_with(ctx, msg, |position| Some(position.saturating_sub(jump))).await;
+}
+
+pub async fn now_playing(_: &Handler, ctx: &Context, msg: &Invocation) {
+    let (Some(guild_id), Some(call)) = (msg.guild_id, get_songbird(ctx, msg).await) else {
+        say!(ctx, msg, "Nothing is playing");
+        return;
+    };
+    let Some(track) = call.lock().await.queue().current() else {
+        say!(ctx, msg, "Nothing is playing");
+        return;
+    };
+
+    let Some(embed) = now_playing_embed(ctx, guild_id, &track).await else {
+        say!(ctx, msg, "Nothing is playing");
+        return;
+    };
+
+    let message = match msg.send_embed(ctx, embed).await {
+        Ok(message) => message,
+        Err(why) => {
+            println!("Error sending message: {:?}", why);
+            return;
+        }
+    };
+
+    let playback = playback_states(ctx).await;
+    playback
+        .lock()
+        .await
+        .entry(guild_id)
+        .or_default()
+        .now_playing = Some(message.id);
+
+    let ctx = ctx.clone();
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(NOW_PLAYING_REFRESH).await;
+
+            let current = playback
+                .lock()
+                .await
+                .get(&guild_id)
+                .and_then(|playback| playback.now_playing);
+            if current != Some(message.id) {
+                break;
+            }
+
+            let Some(embed) = now_playing_embed(&ctx, guild_id, &track).await else {
+                break;
+            };
+
+            let edit = EditMessage::new().embed(embed);
+            if let Err(why) = message
+                .channel_id
+                .edit_message(&ctx.http, message.id, edit)
+                .await
+            {
+                println!("Failed to update now playing: {why}");
+                break;
+            }
+        }
+    });
+}
+
+/// The `nowplaying` embed for `track`, or nothing once it has finished.
+async fn now_playing_embed(
+    ctx: &Context,
+    guild_id: GuildId,
+    track: &TrackHandle,
+) -> Option<CreateEmbed> {
+    let state = track.get_info().await.ok()?;
+    if state.playing.is_done() {
+        return None;
+    }
+
+    let info = track_info(track).await?;
+    let metadata = &info.metadata;
+
+    let mut embed = track_embed("Now Playing", metadata)
+        .description(progress_bar(state.position, metadata.duration));
+
+    if let Some(uploader) = metadata.channel.as_ref().or(metadata.artist.as_ref()) {
+        embed = embed.field("Uploader", uploader, true);
+    }
+    embed = embed.field("Requested by", info.requested_by(), true);
+    if let Some(thumbnail) = &metadata.thumbnail {
+        embed = embed.thumbnail(thumbnail);
+    }
+
+    Some(with_loop_mode(embed, loop_mode(ctx, Some(guild_id)).await))
+}
+
+/// A bar like `▬▬▬🔘▬▬▬▬ 1:23 / 4:56`, or just the elapsed time when the length isn't known.
+fn progress_bar(position: Duration, duration: Option<Duration>) -> String {
+    let Some(duration) = duration.filter(|duration| !duration.is_zero()) else {
+        return format!("`{}`", format_duration(position));
+    };
+
+    let filled =
+        ((position.as_secs_f64() / duration.as_secs_f64()) * PROGRESS_BAR_LENGTH as f64) as usize;
+    let filled = filled.min(PROGRESS_BAR_LENGTH - 1);
+
+    format!(
+        "{}🔘{} `{} / {}`",
+        "▬".repeat(filled),
+        "▬".repeat(PROGRESS_BAR_LENGTH - 1 - filled),
+        format_duration(position),
+        format_duration(duration)
+    )
+}
+
+pub async fn volume(_: &Handler, ctx: &Context, msg: &Invocation) {
+    let Some(guild_id) = msg.guild_id else {
+        say!(ctx, msg, "Volume only works in a server");
+        return;
+    };
+
+    let settings = guild_settings(ctx, Some(guild_id)).await;
+
+    let Some(volume) = try_arg!(ctx, msg, msg.args.get_opt::<usize>(0)) else {
+        say!(ctx, msg, "Volume is {}%", settings.default_volume);
+        return;
+    };
+
+    if volume > settings.max_volume as usize {
+        say!(
+            ctx,
+            msg,
+            "Volume goes from 0 to {}% here",
+            settings.max_volume
+        );
+        return;
+    }
+    let volume = volume as u32;
+
+    // Saved as the server's volume so it outlives the current queue and restarts
+    let result = {
+        let mut store = ctx.data.write().await;
+        let config_handler = store.get_mut::<ConfigContainer>().expect("Missing Config");
+
+        config_handler
+            .update_guild(guild_id, |guild| guild.default_volume = Some(volume))
+            .map_err(|e| e.to_string())
+    };
+
+    if let Err(e) = result {
+        say!(ctx, msg, "Failed to save the volume: {}", e);
+        return;
+    }
+
+    if let Some(call) = get_songbird(ctx, msg).await {
+        for track in call.lock().await.queue().current_queue() {
+            let _ = track.set_volume(volume_scale(volume));
+        }
+    }
+
+    say!(ctx, msg, "Volume is now {}%", volume);
+}
+
+/// Seeks the current song to wherever `target` puts it, given where it is now.
+async fn seek_with(
+    ctx: &Context,
+    msg: &Invocation,
+    target: impl FnOnce(Duration) -> Option<Duration>,
+) {
+    let Some(call) = get_songbird(ctx, msg).await else {
+        say!(ctx, msg, "Nothing is playing");
+        return;
+    };
+    let Some(current) = call.lock().await.queue().current() else {
+        say!(ctx, msg, "Nothing is playing");
+        return;
+    };
+
+    let seekable = track_info(&current)
+        .await
+        .and_then(|info| info.source.is_seekable());
+
+    match seekable {
+        Some(true) => {}
+        Some(false) => {
+            say!(
+                ctx,
+                msg,
+                "This song is streamed in a way that can't be seeked"
+            );
+            return;
+        }
+        None => {
+            say!(ctx, msg, "The song is still loading, try again in a moment");
+            return;
+        }
+    }
+
+    let position = match current.get_info().await {
+        Ok(state) => state.position,
+        Err(why) => {
+            say!(ctx, msg, "Couldn't get the song's position: {}", why);
+            return;
+        }
+    };
+
+    let metadata = track_metadata(&current).await;
+
+    let target = target(position).filter(|target| match metadata.duration {
+        Some(duration) => *target <= duration,
+        None => true,
+    });
+    let Some(target) = target else {
+        say!(ctx, msg, "That's past the end of the song");
+        return;
+    };
+
+    let position = match current.seek(target).result_async().await {
+        Ok(position) => position,
+        Err(why) => {
+            say!(ctx, msg, "Couldn't seek: {}", why);
+            return;
+        }
+    };
+
+    let duration = metadata
+        .duration
+        .map(format_duration)
+        .unwrap_or_else(|| String::from("?"));
+    let embed = track_embed(
+        format!("Seeked to {} / {}", format_duration(position), duration),
+        &metadata,
+    );
+
+    crate::check_msg(msg.send_embed(ctx, embed).await);
+}
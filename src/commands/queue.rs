@@ -0,0 +1,456 @@
+use rand::seq::SliceRandom;
+use serenity::all::{Colour, CreateEmbed, CreateEmbedAuthor};
+use serenity::client::Context;
+
+use crate::commands::args::FromArg;
+use crate::commands::invocation::Invocation;
+use crate::commands::music::get_songbird;
+use crate::commands::music_util::{track_embed, track_info, track_metadata};
+use crate::commands::registry::{BotCommand, Category, CommandOption, CommandRegistry, OptionKind};
+use crate::util::permissions::{permission_level, Permission};
+use crate::util::playback::{loop_mode, playback_states, LoopMode};
+use crate::{command_fn, say, try_arg, Handler};
+
+pub fn register(registry: &mut CommandRegistry) {
+    registry
+        .register(BotCommand {
+            name: "remove",
+            aliases: &["rm"],
+            description: "Removes a song, or a range of songs, from the queue. Only DJs can remove other people's songs",
+            usage: "<position | from-to>",
+            options: &[CommandOption {
+                name: "position",
+                description: "Position in the queue, or a range like 2-5",
+                kind: OptionKind::String,
+                required: true,
+            }],
+            category: Category::Queue,
+            permission: Permission::Everyone,
+            handler: command_fn!(remove),
+        })
+        .register(BotCommand {
+            name: "move",
+            aliases: &["mv"],
+            description: "Moves a song to another position in the queue",
+            usage: "<from> <to>",
+            options: &[
+                CommandOption {
+                    name: "from",
+                    description: "Position of the song to move",
+                    kind: OptionKind::Integer,
+                    required: true,
+                },
+                CommandOption {
+                    name: "to",
+                    description: "Position to move it to",
+                    kind: OptionKind::Integer,
+                    required: true,
+                },
+            ],
+            category: Category::Queue,
+            permission: Permission::Dj,
+            handler: command_fn!(move_track),
+        })
+        .register(BotCommand {
+            name: "clear",
+            aliases: &[],
+            description: "Removes every song after the current one",
+            usage: "",
+            options: &[],
+            category: Category::Queue,
+            permission: Permission::Dj,
+            handler: command_fn!(clear),
+        })
+        .register(BotCommand {
+            name: "skipto",
+            aliases: &[],
+            description: "Skips straight to a song in the queue",
+            usage: "<position>",
+            options: &[CommandOption {
+                name: "position",
+                description: "Position in the queue",
+                kind: OptionKind::Integer,
+                required: true,
+            }],
+            category: Category::Queue,
+            permission: Permission::Dj,
+            handler: command_fn!(skip_to),
+        })
+        .register(BotCommand {
+            name: "shuffle",
+            aliases: &[],
+            description: "Shuffles every song after the current one",
+            usage: "",
+            options: &[],
+            category: Category::Queue,
+            permission: Permission::Dj,
+            handler: command_fn!(shuffle),
+        })
+        .register(BotCommand {
+            name: "loop",
+            aliases: &["repeat"],
+            description: "Shows or changes whether the current song or the whole queue repeats",
+            usage: "[off|track|queue]",
+            options: &[CommandOption {
+                name: "mode",
+                description: "off, track or queue",
+                kind: OptionKind::String,
+                required: false,
+            }],
+            category: Category::Music,
+            permission: Permission::Dj,
+            handler: command_fn!(set_loop),
+        })
+        .register(BotCommand {
+            name: "history",
+            aliases: &["played"],
+            description: "Lists the songs that played recently",
+            usage: "",
+            options: &[],
+            category: Category::Music,
+            permission: Permission::Everyone,
+            handler: command_fn!(history),
+        });
+}
+
+/// Queue positions as shown by `queue`, where 1 is the next song. Either a single position or
+/// an inclusive range like `2-5`.
+#[derive(Clone, Copy, Debug)]
+pub struct Positions {
+    first: usize,
+    last: usize,
+}
+
+impl FromArg for Positions {
+    const NAME: &'static str = "queue position or range (like 2 or 2-5)";
+
+    fn from_arg(arg: &str) -> Option<Self> {
+        let (first, last) = match arg.split_once('-') {
+            Some((first, last)) => (first.trim().parse().ok()?, last.trim().parse().ok()?),
+            None => {
+                let position = arg.parse().ok()?;
+                (position, position)
+            }
+        };
+
+        (first >= 1 && first <= last).then_some(Positions { first, last })
+    }
+}
+
+pub async fn remove(_: &Handler, ctx: &Context, msg: &Invocation) {
+    let positions = try_arg!(ctx, msg, msg.args.get::<Positions>(0, "position"));
+
+    let Some(call) = get_songbird(ctx, msg).await else {
+        say!(ctx, msg, "Nothing is playing");
+        return;
+    };
+    let call = call.lock().await;
+
+    let queue = call.queue().current_queue();
+    if positions.last >= queue.len() {
+        out_of_range(ctx, msg, queue.len()).await;
+        return;
+    }
+
+    // Anyone can take back their own songs, only DJs can remove other people's
+    if permission_level(ctx, msg.guild_id, msg.author.id).await < Permission::Dj {
+        for (position, track) in (positions.first..).zip(&queue[positions.first..=positions.last]) {
+            let requester = track_info(track).await.map(|info| info.requester);
+
+            if requester != Some(msg.author.id) {
+                say!(
+                    ctx,
+                    msg,
+                    "#{} was queued by someone else, only DJs can remove it",
+                    position
+                );
+                return;
+            }
+        }
+    }
+
+    // The queue may have moved on while checking, only remove the tracks that were checked
+    let removed = call.queue().modify_queue(|current| {
+        let expected = queue[positions.first..=positions.last]
+            .iter()
+            .map(|track| track.uuid());
+        let found = current
+            .range(positions.first..=positions.last.min(current.len().saturating_sub(1)))
+            .map(|track| track.uuid());
+
+        if positions.last >= current.len() || !expected.eq(found) {
+            return None;
+        }
+
+        Some(
+            current
+                .drain(positions.first..=positions.last)
+                .collect::<Vec<_>>(),
+        )
+    });
+
+    let Some(removed) = removed else {
+        say!(ctx, msg, "The queue changed, try again");
+        return;
+    };
+
+    for track in &removed {
+        let _ = track.stop();
+    }
+
+    let embed = if let [track] = &removed[..] {
+        track_embed(
+            format!("Removed #{} from the Queue", positions.first),
+            &track_metadata(track).await,
+        )
+    } else {
+        let mut lines = Vec::new();
+        for (position, track) in (positions.first..).zip(&removed) {
+            let metadata = track_metadata(track).await;
+            lines.push(format!(
+                "#{} {}",
+                position,
+                metadata.title.as_deref().unwrap_or("Unknown")
+            ));
+        }
+
+        CreateEmbed::new()
+            .colour(Colour::RED)
+            .author(CreateEmbedAuthor::new(format!(
+                "Removed {} songs from the Queue",
+                removed.len()
+            )))
+            .description(lines.join("\n"))
+    };
+
+    crate::check_msg(msg.send_embed(ctx, embed).await);
+}
+
+pub async fn move_track(_: &Handler, ctx: &Context, msg: &Invocation) {
+    let from = try_arg!(ctx, msg, msg.args.get::<usize>(0, "from"));
+    let to = try_arg!(ctx, msg, msg.args.get::<usize>(1, "to"));
+
+    let Some(call) = get_songbird(ctx, msg).await else {
+        say!(ctx, msg, "Nothing is playing");
+        return;
+    };
+    let call = call.lock().await;
+
+    let moved = call.queue().modify_queue(|queue| {
+        // The current song can't be moved, or moved in front of
+        if from == 0 || to == 0 || from >= queue.len() || to >= queue.len() {
+            return Err(queue.len());
+        }
+
+        let track = queue.remove(from).expect("checked above");
+        let handle = track.handle();
+        queue.insert(to, track);
+
+        Ok(handle)
+    });
+
+    match moved {
+        Ok(track) => {
+            let embed = track_embed(
+                format!("Moved to #{to} in Queue"),
+                &track_metadata(&track).await,
+            );
+            crate::check_msg(msg.send_embed(ctx, embed).await);
+        }
+        Err(len) => out_of_range(ctx, msg, len).await,
+    }
+}
+
+pub async fn clear(_: &Handler, ctx: &Context, msg: &Invocation) {
+    let Some(call) = get_songbird(ctx, msg).await else {
+        say!(ctx, msg, "Nothing is playing");
+        return;
+    };
+    let call = call.lock().await;
+
+    let removed = call
+        .queue()
+        .modify_queue(|queue| queue.drain(1.min(queue.len())..).collect::<Vec<_>>());
+
+    for track in &removed {
+        let _ = track.stop();
+    }
+
+    let embed = CreateEmbed::new()
+        .colour(Colour::RED)
+        .author(CreateEmbedAuthor::new("Cleared the Queue"))
+        .title(format!("Removed {} songs", removed.len()));
+
+    crate::check_msg(msg.send_embed(ctx, embed).await);
+}
+
+pub async fn skip_to(_: &Handler, ctx: &Context, msg: &Invocation) {
+    let position = try_arg!(ctx, msg, msg.args.get::<usize>(0, "position"));
+
+    let Some(call) = get_songbird(ctx, msg).await else {
+        say!(ctx, msg, "Nothing is playing");
+        return;
+    };
+    let call = call.lock().await;
+
+    let skipped = call.queue().modify_queue(|queue| {
+        if position == 0 || position >= queue.len() {
+            return Err(queue.len());
+        }
+
+        // Leave the current song in front so skipping it moves the queue on as usual
+        Ok(queue.drain(1..position).collect::<Vec<_>>())
+    });
+
+    let skipped = match skipped {
+        Ok(skipped) => skipped,
+        Err(len) => {
+            out_of_range(ctx, msg, len).await;
+            return;
+        }
+    };
+
+    for track in &skipped {
+        let _ = track.stop();
+    }
+
+    let next = call.queue().current_queue().get(1).cloned();
+    let _ = call.queue().skip();
+
+    if let Some(next) = next {
+        let embed = track_embed("Now Playing", &track_metadata(&next).await);
+        crate::check_msg(msg.send_embed(ctx, embed).await);
+    }
+}
+
+pub async fn shuffle(_: &Handler, ctx: &Context, msg: &Invocation) {
+    let Some(call) = get_songbird(ctx, msg).await else {
+        say!(ctx, msg, "Nothing is playing");
+        return;
+    };
+    let call = call.lock().await;
+
+    let shuffled = call.queue().modify_queue(|queue| {
+        let start = 1.min(queue.len());
+        let upcoming = &mut queue.make_contiguous()[start..];
+        upcoming.shuffle(&mut rand::thread_rng());
+
+        upcoming.len()
+    });
+
+    let embed = CreateEmbed::new()
+        .colour(Colour::RED)
+        .author(CreateEmbedAuthor::new("Shuffled the Queue"))
+        .title(format!("Shuffled {shuffled} songs"));
+
+    crate::check_msg(msg.send_embed(ctx, embed).await);
+}
+
+pub async fn set_loop(_: &Handler, ctx: &Context, msg: &Invocation) {
+    let Some(guild_id) = msg.guild_id else {
+        say!(ctx, msg, "Looping only works in a server");
+        return;
+    };
+
+    let Some(mode) = try_arg!(ctx, msg, msg.args.get_opt::<LoopMode>(0)) else {
+        say!(ctx, msg, "Loop is {}", loop_mode(ctx, Some(guild_id)).await);
+        return;
+    };
+
+    playback_states(ctx)
+        .await
+        .lock()
+        .await
+        .entry(guild_id)
+        .or_default()
+        .loop_mode = mode;
+
+    // New tracks pick the mode up as they start, the current one needs telling now
+    if let Some(call) = get_songbird(ctx, msg).await {
+        if let Some(current) = call.lock().await.queue().current() {
+            let _ = match mode {
+                LoopMode::Track => current.enable_loop(),
+                LoopMode::Off | LoopMode::Queue => current.disable_loop(),
+            };
+        }
+    }
+
+    say!(ctx, msg, "Loop is now {}", mode);
+}
+
+pub async fn history(_: &Handler, ctx: &Context, msg: &Invocation) {
+    let Some(guild_id) = msg.guild_id else {
+        say!(ctx, msg, "Nothing has played yet");
+        return;
+    };
+
+    let lines = playback_states(ctx)
+        .await
+        .lock()
+        .await
+        .get(&guild_id)
+        .map(|playback| {
+            playback
+                .history
+                .iter()
+                .enumerate()
+                .map(|(i, info)| {
+                    format!(
+                        "`{}.` {} - {} in <#{}>",
+                        i + 1,
+                        info.title(),
+                        info.requested_by(),
+                        info.channel_id
+                    )
+                })
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+
+    if lines.is_empty() {
+        say!(ctx, msg, "Nothing has played yet");
+        return;
+    }
+
+    let embed = CreateEmbed::new()
+        .colour(Colour::RED)
+        .author(CreateEmbedAuthor::new("Recently Played"))
+        .description(lines.join("\n"));
+
+    crate::check_msg(msg.send_embed(ctx, embed).await);
+}
+
+async fn out_of_range(ctx: &Context, msg: &Invocation, len: usize) {
+    if len <= 1 {
+        say!(ctx, msg, "There's nothing queued after the current song");
+    } else {
+        say!(ctx, msg, "Queue positions go from 1 to {}", len - 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn positions(arg: &str) -> Option<(usize, usize)> {
+        Positions::from_arg(arg).map(|positions| (positions.first, positions.last))
+    }
+
+    #[test]
+    fn single_positions() {
+        assert_eq!(positions("1"), Some((1, 1)));
+        assert_eq!(positions("12"), Some((12, 12)));
+        assert_eq!(positions("0"), None);
+        assert_eq!(positions("x"), None);
+    }
+
+    #[test]
+    fn ranges() {
+        assert_eq!(positions("2-5"), Some((2, 5)));
+        assert_eq!(positions("3 - 3"), Some((3, 3)));
+        assert_eq!(positions("5-2"), None);
+        assert_eq!(positions("0-2"), None);
+        assert_eq!(positions("2-"), None);
+        assert_eq!(positions("-2"), None);
+    }
+}
@@ -0,0 +1,146 @@
+use std::future::Future;
+use std::pin::Pin;
+
+use serenity::all::{
+    ChannelType, Command, CommandOptionType, CreateCommand, CreateCommandOption, GuildId,
+};
+use serenity::client::Context;
+
+use super::invocation::Invocation;
+use crate::util::permissions::Permission;
+use crate::Handler;
+
+pub type CommandFuture<'a> = Pin<Box<dyn Future<Output = ()> + Send + 'a>>;
+pub type CommandFn = for<'a> fn(&'a Handler, &'a Context, &'a Invocation) -> CommandFuture<'a>;
+
+/// Wraps an `async fn(&Handler, &Context, &Invocation)` so it can be stored in a [`BotCommand`].
+#[macro_export]
+macro_rules! command_fn {
+    ($func:path) => {
+        |handler, ctx, msg| Box::pin($func(handler, ctx, msg))
+    };
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OptionKind {
+    String,
+    Integer,
+    /// A user or a role.
+    Mentionable,
+    /// A voice or Stage channel.
+    Channel,
+    /// An on/off switch, `--name` as a prefix command.
+    Flag,
+}
+
+/// What a command is for, so per-guild settings can restrict whole groups of commands.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Category {
+    General,
+    /// Limited to a guild's music channels, if it has any.
+    Music,
+    /// Music commands that change the queue, which DJ-only mode limits to DJs.
+    Queue,
+}
+
+/// A typed argument, shown as a slash command option.
+pub struct CommandOption {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub kind: OptionKind,
+    pub required: bool,
+}
+
+pub struct BotCommand {
+    pub name: &'static str,
+    pub aliases: &'static [&'static str],
+    pub description: &'static str,
+    pub usage: &'static str,
+    pub options: &'static [CommandOption],
+    pub category: Category,
+    pub permission: Permission,
+    pub handler: CommandFn,
+}
+
+impl BotCommand {
+    pub fn create_command(&self) -> CreateCommand {
+        // Music commands need a voice channel, so keep them out of DMs
+        let command = CreateCommand::new(self.name)
+            .description(self.description)
+            .dm_permission(self.category == Category::General);
+
+        self.options.iter().fold(command, |command, option| {
+            let kind = match option.kind {
+                OptionKind::String => CommandOptionType::String,
+                OptionKind::Integer => CommandOptionType::Integer,
+                OptionKind::Mentionable => CommandOptionType::Mentionable,
+                OptionKind::Channel => CommandOptionType::Channel,
+                OptionKind::Flag => CommandOptionType::Boolean,
+            };
+
+            let mut created = CreateCommandOption::new(kind, option.name, option.description)
+                .required(option.required);
+            if option.kind == OptionKind::Channel {
+                created = created.channel_types(vec![ChannelType::Voice, ChannelType::Stage]);
+            }
+
+            command.add_option(created)
+        })
+    }
+}
+
+#[derive(Default)]
+pub struct CommandRegistry {
+    commands: Vec<BotCommand>,
+}
+
+impl CommandRegistry {
+    pub fn register(&mut self, command: BotCommand) -> &mut Self {
+        for name in std::iter::once(&command.name).chain(command.aliases) {
+            if let Some(existing) = self.find(name) {
+                panic!(
+                    "Command name '{}' registered by both '{}' and '{}'",
+                    name, existing.name, command.name
+                );
+            }
+        }
+
+        self.commands.push(command);
+        self
+    }
+
+    pub fn find(&self, name: &str) -> Option<&BotCommand> {
+        self.commands
+            .iter()
+            .find(|command| command.name == name || command.aliases.contains(&name))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &BotCommand> {
+        self.commands.iter()
+    }
+
+    /// Registers every command as a slash command, globally or only in `guilds` if any are given.
+    /// Guild commands update instantly, global ones can take a while to show up.
+    pub async fn register_slash_commands(&self, ctx: &Context, guilds: &[GuildId]) {
+        let commands = self
+            .iter()
+            .map(BotCommand::create_command)
+            .collect::<Vec<_>>();
+
+        if guilds.is_empty() {
+            match Command::set_global_commands(&ctx.http, commands).await {
+                Ok(registered) => println!("Registered {} global commands", registered.len()),
+                Err(e) => println!("Failed to register global commands: {e}"),
+            }
+        } else {
+            for guild_id in guilds {
+                match guild_id.set_commands(&ctx.http, commands.clone()).await {
+                    Ok(registered) => {
+                        println!("Registered {} commands in {guild_id}", registered.len())
+                    }
+                    Err(e) => println!("Failed to register commands in {guild_id}: {e}"),
+                }
+            }
+        }
+    }
+}
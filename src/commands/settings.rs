@@ -0,0 +1,450 @@
+use serenity::all::{ChannelId, Colour, CreateEmbed, RoleId};
+use serenity::client::Context;
+use std::time::Duration;
+
+use crate::commands::args::{format_duration, ArgError, FromArg};
+use crate::commands::invocation::Invocation;
+use crate::commands::registry::{BotCommand, Category, CommandOption, CommandRegistry, OptionKind};
+use crate::util::guild_config::{guild_settings, GuildConfig, VOLUME_LIMIT};
+use crate::util::permissions::Permission;
+use crate::{command_fn, say, try_arg, ConfigContainer, Handler};
+
+/// A change to one setting, applied through `ConfigHandler::update_guild`.
+type SettingUpdate = Box<dyn FnOnce(&mut GuildConfig) + Send>;
+
+pub fn register(registry: &mut CommandRegistry) {
+    registry
+        .register(BotCommand {
+            name: "settings",
+            aliases: &["guildconfig"],
+            description: "Shows or changes this server's settings",
+            usage: "[set <key> <value> | unset <key>]",
+            options: &[
+                CommandOption {
+                    name: "action",
+                    description: "set or unset, leave empty to show the settings",
+                    kind: OptionKind::String,
+                    required: false,
+                },
+                CommandOption {
+                    name: "key",
+                    description:
+                        "Setting name, like prefix, dj_role, default_volume or music_channels",
+                    kind: OptionKind::String,
+                    required: false,
+                },
+                CommandOption {
+                    name: "value",
+                    description: "New value for set",
+                    kind: OptionKind::String,
+                    required: false,
+                },
+            ],
+            category: Category::General,
+            permission: Permission::Admin,
+            handler: command_fn!(settings),
+        })
+        .register(BotCommand {
+            name: "djonly",
+            aliases: &[],
+            description: "Shows or changes whether only DJs can change the queue",
+            usage: "[on|off]",
+            options: &[CommandOption {
+                name: "enabled",
+                description: "on or off",
+                kind: OptionKind::String,
+                required: false,
+            }],
+            category: Category::General,
+            permission: Permission::Admin,
+            handler: command_fn!(dj_only),
+        })
+        .register(BotCommand {
+            name: "247",
+            aliases: &["stay"],
+            description: "Shows or changes whether the bot stays in voice even when idle",
+            usage: "[on|off]",
+            options: &[CommandOption {
+                name: "enabled",
+                description: "on or off",
+                kind: OptionKind::String,
+                required: false,
+            }],
+            category: Category::General,
+            permission: Permission::Admin,
+            handler: command_fn!(always_on),
+        });
+}
+
+pub async fn settings(_: &Handler, ctx: &Context, msg: &Invocation) {
+    let Some(guild_id) = msg.guild_id else {
+        say!(ctx, msg, "Settings can only be changed in a server");
+        return;
+    };
+
+    let action = try_arg!(ctx, msg, msg.args.get_opt::<String>(0));
+
+    let update: SettingUpdate = match action.as_deref() {
+        None => {
+            show_settings(ctx, msg).await;
+            return;
+        }
+        Some("set") => {
+            let key = try_arg!(ctx, msg, msg.args.get::<String>(1, "key"));
+            try_arg!(ctx, msg, parse_setting(msg, &key))
+        }
+        Some("unset") => {
+            let key = try_arg!(ctx, msg, msg.args.get::<String>(1, "key"));
+            try_arg!(ctx, msg, unset_setting(&key))
+        }
+        Some(other) => {
+            say!(
+                ctx,
+                msg,
+                "Unknown action `{}`, expected set or unset",
+                other
+            );
+            return;
+        }
+    };
+
+    let result = {
+        let mut store = ctx.data.write().await;
+        let config_handler = store.get_mut::<ConfigContainer>().expect("Missing Config");
+
+        config_handler
+            .update_guild(guild_id, update)
+            .map_err(|e| e.to_string())
+    };
+
+    match result {
+        Ok(()) => show_settings(ctx, msg).await,
+        Err(e) => say!(ctx, msg, "Failed to save settings: {}", e),
+    }
+}
+
+pub async fn dj_only(_: &Handler, ctx: &Context, msg: &Invocation) {
+    let Some(guild_id) = msg.guild_id else {
+        say!(ctx, msg, "DJ-only mode only works in a server");
+        return;
+    };
+
+    let Some(enabled) = try_arg!(ctx, msg, msg.args.get_opt::<bool>(0)) else {
+        let enabled = guild_settings(ctx, Some(guild_id)).await.dj_only;
+        say!(
+            ctx,
+            msg,
+            "DJ-only mode is {}",
+            if enabled { "on" } else { "off" }
+        );
+        return;
+    };
+
+    let result = {
+        let mut store = ctx.data.write().await;
+        let config_handler = store.get_mut::<ConfigContainer>().expect("Missing Config");
+
+        config_handler
+            .update_guild(guild_id, |guild| guild.dj_only = enabled)
+            .map_err(|e| e.to_string())
+            .map(|()| {
+                config_handler
+                    .read_config()
+                    .guilds
+                    .get(&guild_id)
+                    .is_some_and(GuildConfig::grants_dj)
+            })
+    };
+
+    match result {
+        Ok(true) if enabled => say!(
+            ctx,
+            msg,
+            "DJ-only mode is on, only DJs can change the queue"
+        ),
+        // Without any DJs everyone counts as one, which DJ-only mode doesn't go along with
+        Ok(false) if enabled => say!(
+            ctx,
+            msg,
+            "DJ-only mode is on, but nobody has DJ yet so only admins can change the queue. \
+             Set a dj_role or grant DJ to let others"
+        ),
+        Ok(_) => say!(ctx, msg, "DJ-only mode is off"),
+        Err(e) => say!(ctx, msg, "Failed to save settings: {}", e),
+    }
+}
+
+pub async fn always_on(_: &Handler, ctx: &Context, msg: &Invocation) {
+    let Some(guild_id) = msg.guild_id else {
+        say!(ctx, msg, "24/7 mode only works in a server");
+        return;
+    };
+
+    let Some(enabled) = try_arg!(ctx, msg, msg.args.get_opt::<bool>(0)) else {
+        let enabled = guild_settings(ctx, Some(guild_id)).await.always_on;
+        say!(
+            ctx,
+            msg,
+            "24/7 mode is {}",
+            if enabled { "on" } else { "off" }
+        );
+        return;
+    };
+
+    let result = {
+        let mut store = ctx.data.write().await;
+        let config_handler = store.get_mut::<ConfigContainer>().expect("Missing Config");
+
+        config_handler
+            .update_guild(guild_id, |guild| guild.always_on = enabled)
+            .map_err(|e| e.to_string())
+    };
+
+    match result {
+        Ok(()) if enabled => say!(
+            ctx,
+            msg,
+            "24/7 mode is on, the bot won't leave for being idle"
+        ),
+        Ok(()) => say!(ctx, msg, "24/7 mode is off"),
+        Err(e) => say!(ctx, msg, "Failed to save settings: {}", e),
+    }
+}
+
+/// Parses `set <key> <value>` into the change it makes.
+fn parse_setting(msg: &Invocation, key: &str) -> Result<SettingUpdate, ArgError> {
+    Ok(match key {
+        "prefix" => {
+            let prefix = msg.args.get::<String>(2, "prefix")?;
+
+            if prefix.is_empty() || prefix.contains(char::is_whitespace) {
+                return Err(ArgError::Invalid {
+                    value: prefix,
+                    expected: "prefix without spaces",
+                });
+            }
+
+            Box::new(move |guild| guild.command_prefix = Some(prefix))
+        }
+        "dj_role" => {
+            let role = msg.args.get::<RoleId>(2, "role")?;
+            Box::new(move |guild| guild.dj_role = Some(role))
+        }
+        "default_volume" => {
+            let volume = parse_volume(msg)?;
+            Box::new(move |guild| guild.default_volume = Some(volume))
+        }
+        "max_volume" => {
+            let volume = parse_volume(msg)?;
+            Box::new(move |guild| guild.max_volume = Some(volume))
+        }
+        "skip_vote_percent" => {
+            let percent = msg.args.get::<usize>(2, "percent")?;
+
+            if !(1..=100).contains(&percent) {
+                return Err(ArgError::Invalid {
+                    value: percent.to_string(),
+                    expected: "percent between 1 and 100",
+                });
+            }
+
+            Box::new(move |guild| guild.skip_vote_percent = Some(percent as u32))
+        }
+        "max_queue_length" => {
+            let length = msg.args.get::<usize>(2, "queue length")?;
+            Box::new(move |guild| guild.max_queue_length = Some(length))
+        }
+        "max_tracks_per_user" => {
+            let max = msg.args.get::<usize>(2, "songs per person")?;
+            Box::new(move |guild| guild.max_tracks_per_user = Some(max))
+        }
+        "max_track_length" => {
+            let length = msg.args.get::<Duration>(2, "length")?;
+            Box::new(move |guild| guild.max_track_length = Some(length.as_secs()))
+        }
+        "max_playlist_length" => {
+            let length = msg.args.get::<usize>(2, "playlist length")?;
+            Box::new(move |guild| guild.max_playlist_length = Some(length))
+        }
+        "idle_timeout" => {
+            let timeout = msg.args.get::<Duration>(2, "timeout")?;
+            Box::new(move |guild| guild.idle_timeout = Some(timeout.as_secs()))
+        }
+        "music_channels" => {
+            // Slash commands pass every channel in one value, so split them here
+            let channels = msg
+                .args
+                .rest(2, "channels")?
+                .split_whitespace()
+                .map(|channel| {
+                    ChannelId::from_arg(channel).ok_or_else(|| ArgError::Invalid {
+                        value: channel.to_string(),
+                        expected: ChannelId::NAME,
+                    })
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+
+            Box::new(move |guild| guild.music_channels = channels)
+        }
+        "announce_channel" => {
+            let channel = msg.args.get::<ChannelId>(2, "channel")?;
+            Box::new(move |guild| guild.announce_channel = Some(channel))
+        }
+        _ => return Err(unknown_key(key)),
+    })
+}
+
+fn unset_setting(key: &str) -> Result<SettingUpdate, ArgError> {
+    Ok(match key {
+        "prefix" => Box::new(|guild| guild.command_prefix = None),
+        "dj_role" => Box::new(|guild| guild.dj_role = None),
+        "default_volume" => Box::new(|guild| guild.default_volume = None),
+        "max_volume" => Box::new(|guild| guild.max_volume = None),
+        "skip_vote_percent" => Box::new(|guild| guild.skip_vote_percent = None),
+        "max_queue_length" => Box::new(|guild| guild.max_queue_length = None),
+        "max_tracks_per_user" => Box::new(|guild| guild.max_tracks_per_user = None),
+        "max_track_length" => Box::new(|guild| guild.max_track_length = None),
+        "max_playlist_length" => Box::new(|guild| guild.max_playlist_length = None),
+        "idle_timeout" => Box::new(|guild| guild.idle_timeout = None),
+        "music_channels" => Box::new(|guild| guild.music_channels.clear()),
+        "announce_channel" => Box::new(|guild| guild.announce_channel = None),
+        _ => return Err(unknown_key(key)),
+    })
+}
+
+fn parse_volume(msg: &Invocation) -> Result<u32, ArgError> {
+    let volume = msg.args.get::<usize>(2, "volume")?;
+
+    if volume > VOLUME_LIMIT as usize {
+        return Err(ArgError::Invalid {
+            value: volume.to_string(),
+            expected: "volume between 0 and 200",
+        });
+    }
+
+    Ok(volume as u32)
+}
+
+fn unknown_key(key: &str) -> ArgError {
+    ArgError::Invalid {
+        value: key.to_string(),
+        expected: "setting (prefix, dj_role, default_volume, max_volume, skip_vote_percent, max_queue_length, max_tracks_per_user, max_track_length, max_playlist_length, idle_timeout, music_channels or announce_channel)",
+    }
+}
+
+/// Shows the effective settings, marking which ones this server overrides.
+async fn show_settings(ctx: &Context, msg: &Invocation) {
+    let (settings, overrides) = {
+        let store = ctx.data.read().await;
+        let config = store
+            .get::<ConfigContainer>()
+            .expect("Missing Config")
+            .read_config();
+
+        (
+            config.guild_settings(msg.guild_id),
+            msg.guild_id
+                .and_then(|guild_id| config.guilds.get(&guild_id).cloned())
+                .unwrap_or_default(),
+        )
+    };
+
+    let source = |overridden: bool| if overridden { "server" } else { "default" };
+
+    let channels = settings
+        .music_channels
+        .iter()
+        .map(|channel| format!("<#{channel}>"))
+        .collect::<Vec<_>>();
+
+    let lines = [
+        format!(
+            "**prefix**: `{}` ({})",
+            settings.command_prefix,
+            source(overrides.command_prefix.is_some())
+        ),
+        format!(
+            "**dj_role**: {}",
+            settings
+                .dj_role
+                .map_or(String::from("none"), |role| format!("<@&{role}>"))
+        ),
+        format!(
+            "**default_volume**: {}% ({})",
+            settings.default_volume,
+            source(overrides.default_volume.is_some())
+        ),
+        format!(
+            "**max_volume**: {}% ({})",
+            settings.max_volume,
+            source(overrides.max_volume.is_some())
+        ),
+        format!(
+            "**skip_vote_percent**: {}% ({})",
+            settings.skip_vote_percent,
+            source(overrides.skip_vote_percent.is_some())
+        ),
+        format!(
+            "**max_queue_length**: {} ({})",
+            settings
+                .max_queue_length
+                .map_or(String::from("unlimited"), |length| length.to_string()),
+            source(overrides.max_queue_length.is_some())
+        ),
+        format!(
+            "**max_tracks_per_user**: {} ({})",
+            settings
+                .max_tracks_per_user
+                .map_or(String::from("unlimited"), |max| max.to_string()),
+            source(overrides.max_tracks_per_user.is_some())
+        ),
+        format!(
+            "**max_track_length**: {} ({})",
+            settings
+                .max_track_length
+                .map_or(String::from("unlimited"), format_duration),
+            source(overrides.max_track_length.is_some())
+        ),
+        format!(
+            "**max_playlist_length**: {} ({})",
+            settings
+                .max_playlist_length
+                .map_or(String::from("unlimited"), |max| max.to_string()),
+            source(overrides.max_playlist_length.is_some())
+        ),
+        format!(
+            "**dj_only**: {}",
+            if settings.dj_only { "on" } else { "off" }
+        ),
+        format!(
+            "**idle_timeout**: {} ({})",
+            if settings.always_on {
+                String::from("never, 24/7 mode is on")
+            } else {
+                format_duration(settings.idle_timeout)
+            },
+            source(overrides.idle_timeout.is_some())
+        ),
+        format!(
+            "**music_channels**: {}",
+            if channels.is_empty() {
+                String::from("any")
+            } else {
+                channels.join(", ")
+            }
+        ),
+        format!(
+            "**announce_channel**: {}",
+            settings
+                .announce_channel
+                .map_or(String::from("none"), |channel| format!("<#{channel}>"))
+        ),
+    ];
+
+    let embed = CreateEmbed::new()
+        .colour(Colour::RED)
+        .title("Server settings")
+        .description(lines.join("\n"));
+
+    crate::check_msg(msg.send_embed(ctx, embed).await);
+}
@@ -4,10 +4,10 @@ mod util;
 use std::env;
 use std::process::Command;
 
-use commands::music::play;
-
 use serenity::async_trait;
+use serenity::model::application::Interaction;
 use serenity::model::channel::Message;
+use serenity::model::gateway::Ready;
 use serenity::prelude::*;
 use serenity::Result as SerenityResult;
 
@@ -15,67 +15,173 @@ use clap::Parser;
 
 use songbird::SerenityInit;
 
-use crate::commands::general::*;
-use crate::commands::music::*;
+use crate::commands::general;
+use crate::commands::help;
+use crate::commands::invocation::Invocation;
+use crate::commands::music;
+use crate::commands::pagination::PageButton;
+use crate::commands::permissions;
+use crate::commands::playback;
+use crate::commands::queue;
+use crate::commands::registry::{BotCommand, Category, CommandRegistry};
+use crate::commands::settings;
 use crate::util::config::*;
+use crate::util::config_overrides::ConfigOverrides;
+use crate::util::guild_config::guild_settings;
+use crate::util::idle::watch_idle;
+use crate::util::permissions::{has_permission, is_explicit_dj};
+use crate::util::playback::{playback_states, PlaybackStates};
+use crate::util::saved_queues::{keep_saving_queues, restore_queues, save_queues};
 use crate::util::typemap::*;
 
-struct Handler;
+struct Handler {
+    commands: CommandRegistry,
+}
 
 static VERSION: &str = "0.0.7";
 
 #[macro_export]
 macro_rules! say {
     ($ctx:expr, $msg:expr, $($arg:tt)*) => {{
-        crate::check_msg($msg.channel_id.say($ctx.http(), format!($($arg)*)).await);
+        $crate::check_msg($msg.say($ctx, format!($($arg)*)).await);
     }}
 }
 
 #[async_trait]
 impl EventHandler for Handler {
     async fn message(&self, ctx: Context, msg: Message) {
-        let prefix = {
+        let prefix = guild_settings(&ctx, msg.guild_id).await.command_prefix;
+
+        if msg.content.starts_with(&prefix) {
+            // Bot should not respond to other bots for now
+            if msg.author.bot {
+                return;
+            }
+
+            let (command_name, args) = match msg.content.split_once(' ') {
+                Some((first, rest)) => (&first[prefix.len()..], rest),
+                None => (&msg.content[prefix.len()..], ""),
+            };
+
+            let Some(command) = self.commands.find(command_name) else {
+                return;
+            };
+
+            let args = args.to_string();
+            let invocation = Invocation::from_message(msg, &prefix, command, &args);
+            self.run_command(&ctx, command, &invocation).await;
+        }
+    }
+
+    async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
+        let interaction = match interaction {
+            Interaction::Command(interaction) => interaction,
+            Interaction::Component(interaction) => {
+                if let Some(button) = PageButton::parse(&interaction.data.custom_id) {
+                    match button.kind.as_str() {
+                        "help" => help::turn_page(self, &ctx, &interaction, &button).await,
+                        "queue" => music::turn_queue_page(&ctx, &interaction, &button).await,
+                        _ => println!("Unknown page button {}", interaction.data.custom_id),
+                    }
+                }
+                return;
+            }
+            _ => return,
+        };
+
+        let Some(command) = self.commands.find(&interaction.data.name) else {
+            println!("Unknown slash command {}", interaction.data.name);
+            return;
+        };
+
+        // Discord wants an answer within 3 seconds, which play can't promise
+        if let Err(e) = interaction.defer(&ctx.http).await {
+            println!("Failed to defer interaction: {e}");
+            return;
+        }
+
+        let invocation = Invocation::from_interaction(interaction, command);
+        self.run_command(&ctx, command, &invocation).await;
+        invocation.finish(&ctx).await;
+    }
+
+    async fn ready(&self, ctx: Context, ready: Ready) {
+        println!("{} is connected", ready.user.name);
+
+        let guilds = {
             let store = ctx.data.read().await;
 
             store
                 .get::<ConfigContainer>()
                 .expect("missing config")
                 .read_config()
-                .command_prefix
+                .slash_command_guilds
                 .clone()
         };
 
-        if msg.content.starts_with(&prefix) {
-            // Bot should not respond to other bots for now
-            if msg.author.bot {
+        self.commands.register_slash_commands(&ctx, &guilds).await;
+
+        restore_queues(&ctx).await;
+    }
+}
+
+impl Handler {
+    async fn run_command(&self, ctx: &Context, command: &BotCommand, invocation: &Invocation) {
+        if !has_permission(
+            ctx,
+            invocation.guild_id,
+            invocation.author.id,
+            command.permission,
+        )
+        .await
+        {
+            say!(ctx, invocation, "Permission Denied.");
+            return;
+        }
+
+        if command.category != Category::General {
+            let settings = guild_settings(ctx, invocation.guild_id).await;
+
+            if !settings.allows_music_in(invocation.channel_id) {
+                let channels = settings
+                    .music_channels
+                    .iter()
+                    .map(|channel| format!("<#{channel}>"))
+                    .collect::<Vec<_>>();
+
+                say!(
+                    ctx,
+                    invocation,
+                    "Music commands can only be used in {}",
+                    channels.join(", ")
+                );
                 return;
             }
 
-            let command = match msg.content.split_once(' ') {
-                Some((first, _)) => &first[prefix.len()..],
-                None => &msg.content[prefix.len()..],
-            };
+            if command.category == Category::Queue
+                && settings.dj_only
+                && !is_explicit_dj(ctx, invocation.guild_id, invocation.author.id).await
+            {
+                say!(
+                    ctx,
+                    invocation,
+                    "DJ-only mode is on, only DJs can change the queue"
+                );
+                return;
+            }
 
-            match command {
-                "ping" => ping(self, &ctx, &msg).await,
-                "play" => play(self, &ctx, &msg).await,
-                "stop" => stop(self, &ctx, &msg).await,
-                "pause" => pause(self, &ctx, &msg).await,
-                "join" => {
-                    let _ = join(&ctx, &msg).await;
-                }
-                "queue" => queue(self, &ctx, &msg).await,
-                "skip" => skip(self, &ctx, &msg).await,
-                "edontime" => edon_time(self, &ctx, &msg).await,
-                "edoncount" => edon_time_count(self, &ctx, &msg).await,
-                "update" => update(self, &ctx, &msg).await,
-                "yt_test" => yt_test(self, &ctx, &msg).await,
-                "update_config" => update_config(self, &ctx, &msg).await,
-                "test_parse" => test_parse(self, &ctx, &msg).await,
-                "log_config" => log_config(self, &ctx, &msg).await,
-                _ => {}
+            if let Some(guild_id) = invocation.guild_id {
+                playback_states(ctx)
+                    .await
+                    .lock()
+                    .await
+                    .entry(guild_id)
+                    .or_default()
+                    .text_channel = Some(invocation.channel_id);
             }
         }
+
+        (command.handler)(self, ctx, invocation).await;
     }
 }
 
@@ -87,14 +193,19 @@ struct Args {
     child: bool,
     #[clap(default_value = "config.json")]
     config_path: String,
+    #[command(flatten)]
+    overrides: ConfigOverrides,
 }
 
 fn main() {
     let args = Args::parse();
-    let config = ConfigHandler::load_config_file(&args.config_path).expect(&format!(
-        "ERROR: loading config failed, aborting! {:?}",
-        &args.config_path
-    ));
+    let config = ConfigHandler::load_config_file(&args.config_path, args.overrides.clone())
+        .unwrap_or_else(|_| {
+            panic!(
+                "ERROR: loading config failed, aborting! {:?}",
+                &args.config_path
+            )
+        });
 
     println!("Config loaded!");
 
@@ -106,9 +217,10 @@ fn main() {
         let path = std::env::current_exe().unwrap();
         println!("Starting Parent Instance");
 
+        // The child parses the same arguments, so config overrides carry over
         while Command::new(&path)
             .arg("--child")
-            .arg(&args.config_path)
+            .args(env::args_os().skip(1))
             .status()
             .expect("failed to execute process")
             .success()
@@ -121,7 +233,7 @@ fn main() {
 }
 
 #[tokio::main]
-async fn run_bot(config: ConfigHandler) {
+async fn run_bot(mut config: ConfigHandler) {
     println!("Starting...");
     config.print_state();
     config.save_state().expect("Error saving config");
@@ -142,13 +254,32 @@ async fn run_bot(config: ConfigHandler) {
         new_token
     };
 
+    let mut commands = CommandRegistry::default();
+    help::register(&mut commands);
+    general::register(&mut commands);
+    permissions::register(&mut commands);
+    settings::register(&mut commands);
+    music::register(&mut commands);
+    queue::register(&mut commands);
+    playback::register(&mut commands);
+
+    println!(
+        "Registered commands: {}",
+        commands
+            .iter()
+            .map(|command| command.name)
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+
     println!("Creating Client");
     // Create a new instance of the Client, logging in as a bot.
     let mut client = Client::builder(token_to_use, intents)
-        .event_handler(Handler)
+        .event_handler(Handler { commands })
         .register_songbird()
         .type_map_insert::<HttpKey>(reqwest::Client::new())
         .type_map_insert::<ConfigContainer>(config)
+        .type_map_insert::<PlaybackContainer>(PlaybackStates::default())
         .await
         .expect("Err creating client");
 
@@ -157,6 +288,14 @@ async fn run_bot(config: ConfigHandler) {
         data.insert::<ShardManagerContainer>(client.shard_manager.clone());
     }
 
+    tokio::spawn(watch_config(client.data.clone()));
+    tokio::spawn(keep_saving_queues(client.data.clone()));
+    tokio::spawn(watch_idle(
+        client.data.clone(),
+        client.cache.clone(),
+        client.http.clone(),
+    ));
+
     println!("Starting Listener");
 
     // Start listening for events by starting a single shard
@@ -164,6 +303,11 @@ async fn run_bot(config: ConfigHandler) {
         println!("Client error: {why:?}");
     }
 
+    // The next child picks these up again
+    if let Err(e) = save_queues(&client.data).await {
+        println!("Failed to save queues: {e}");
+    }
+
     println!("Ending Listener");
 }
 
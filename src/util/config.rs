@@ -1,62 +1,227 @@
 use serde::ser::Error;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use serenity::all::UserId;
+use serenity::all::{GuildId, UserId};
 
-use std::fs::File;
-use std::io::{BufReader, BufWriter, Write};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use serenity::prelude::TypeMap;
+use tokio::sync::RwLock;
+
+use super::config_format::ConfigFormat;
+use super::config_migrations::{config_version, migrate, CONFIG_VERSION};
+use super::config_overrides::ConfigOverrides;
+use super::guild_config::GuildConfig;
+use crate::ConfigContainer;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(default)]
 pub struct Config {
+    /// Layout version of the file, see `config_migrations`.
+    pub config_version: u32,
     pub bot_name: String,
     pub command_prefix: String,
+    /// Bot owners, allowed to run every command everywhere. Nobody by default, add your Discord
+    /// user ID here or pass `--auth-users`/`HAL_AUTH_USERS`.
     pub auth_users: Vec<UserId>,
     pub yt_api_key: String,
     pub discord_api_key: String,
     pub edon_count: usize,
+    /// Guilds to register slash commands in. Empty registers them globally.
+    pub slash_command_guilds: Vec<GuildId>,
+    /// Volume new tracks start at, in percent.
+    pub default_volume: u32,
+    /// Highest volume `volume` allows, in percent.
+    pub max_volume: u32,
+    /// Share of listeners, in percent, that have to vote before `skip` skips.
+    pub skip_vote_percent: u32,
+    pub max_queue_length: Option<usize>,
+    /// How many songs one person can have queued at once.
+    pub max_tracks_per_user: Option<usize>,
+    /// Longest song that can be queued, in seconds.
+    pub max_track_length: Option<u64>,
+    /// How many songs of a playlist are queued at most.
+    pub max_playlist_length: Option<usize>,
+    /// How long, in seconds, the bot stays in a voice channel with nothing to do.
+    pub idle_timeout: u64,
+    pub guilds: HashMap<GuildId, GuildConfig>,
+}
+
+impl Config {
+    /// A copy with the API keys blanked out, for logging.
+    pub fn redacted(&self) -> Self {
+        let hidden = String::from("(hidden)");
+
+        Self {
+            yt_api_key: hidden.clone(),
+            discord_api_key: hidden,
+            ..self.clone()
+        }
+    }
+}
+
+/// Whether a config key holds a credential, which is never logged or posted.
+pub fn is_secret(key: &str) -> bool {
+    key.ends_with("api_key")
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
+            config_version: CONFIG_VERSION,
             bot_name: String::from("unnamed bot"),
             command_prefix: String::from("!"),
-            auth_users: vec![UserId::new(95637120575614976)],
+            auth_users: Vec::new(),
             yt_api_key: String::from(""),
             discord_api_key: String::from(""),
             edon_count: 0,
+            slash_command_guilds: Vec::new(),
+            default_volume: 100,
+            max_volume: 200,
+            skip_vote_percent: 50,
+            max_queue_length: None,
+            max_tracks_per_user: None,
+            max_track_length: None,
+            max_playlist_length: None,
+            idle_timeout: 300,
+            guilds: HashMap::new(),
         }
     }
 }
 
+/// How many copies of earlier config files are kept, as `<path>.bak.1` (newest) and up.
+pub const CONFIG_BACKUPS: usize = 5;
+
+/// How often the config file is checked for edits.
+const RELOAD_INTERVAL: Duration = Duration::from_secs(2);
+
+/// How many earlier states `config undo` can go back through.
+const HISTORY_LENGTH: usize = 20;
+
 #[derive(Default)]
 pub struct ConfigHandler {
     config_path: String,
     config: Config,
     state: Value,
+    /// Applied on top of the state, but never saved into it.
+    overrides: ConfigOverrides,
+    /// Earlier states, oldest first.
+    history: Vec<Value>,
+    /// When the file was last read or written by this handler.
+    disk_modified: Option<SystemTime>,
+    /// When the file was last edited into something that doesn't load, so it's only reported once.
+    rejected_modified: Option<SystemTime>,
+}
+
+/// One value changed by [`ConfigHandler::set_path`].
+#[derive(Debug)]
+pub struct ConfigChange {
+    pub path: String,
+    pub old: Option<Value>,
+    pub new: Value,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The path doesn't name anything in [`Config`], so the value would be ignored.
+    UnknownKey(String),
+    /// The value doesn't fit the type of the setting at the path.
+    Invalid {
+        path: String,
+        reason: String,
+    },
+    /// The file was edited on disk and the edit doesn't load.
+    InvalidFile(String),
+    Save(std::io::Error),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::UnknownKey(path) => write!(f, "`{path}` isn't a config setting"),
+            ConfigError::Invalid { path, reason } => {
+                write!(f, "Invalid value for `{path}`: {reason}")
+            }
+            ConfigError::InvalidFile(reason) => {
+                write!(f, "The config file was edited and doesn't load: {reason}")
+            }
+            ConfigError::Save(e) => write!(f, "Failed to save config: {e}"),
+        }
+    }
 }
 
+impl std::error::Error for ConfigError {}
+
 impl ConfigHandler {
-    pub fn load_config_file(config_path: &str) -> Result<Self, Box<dyn std::error::Error + '_>> {
-        let file = File::open(config_path)?;
+    /// Loads the config, falling back to the newest backup that still parses if the file is
+    /// missing or broken. A broken file is kept next to it as `<path>.corrupt`.
+    pub fn load_config_file(
+        config_path: &str,
+        overrides: ConfigOverrides,
+    ) -> Result<Self, Box<dyn std::error::Error + '_>> {
+        let format = ConfigFormat::from_path(config_path);
+        let (state, config) = match read_config_file(config_path, format) {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                println!("Failed to load {config_path}: {e}");
 
-        let reader = BufReader::new(file);
-        let loaded_config: Value = serde_json::from_reader(reader)?;
+                let Some((backup, loaded)) = (1..=CONFIG_BACKUPS)
+                    .map(|i| backup_path(config_path, i))
+                    .find_map(|backup| {
+                        read_config_file(&backup, format)
+                            .ok()
+                            .map(|loaded| (backup, loaded))
+                    })
+                else {
+                    return Err(e);
+                };
+
+                println!("Recovered config from {backup}");
+
+                if Path::new(config_path).exists() {
+                    fs::rename(config_path, format!("{config_path}.corrupt"))?;
+                }
+
+                loaded
+            }
+        };
 
         let mut output = ConfigHandler {
             config_path: config_path.to_string(),
-            config: Config::deserialize(&loaded_config)?,
-            state: loaded_config,
+            config,
+            state,
+            overrides,
+            history: Vec::new(),
+            disk_modified: None,
+            rejected_modified: None,
         };
+        output.disk_modified = output.file_modified();
+        output.overrides.apply(&mut output.config);
+
+        println!("{:?}", output.config.redacted());
 
-        println!("{:?}", output.config);
+        if output.config.auth_users.is_empty() {
+            println!(
+                "No bot owners are set, add your user ID to auth_users or pass --auth-users to \
+                 use owner commands"
+            );
+        }
 
         output.update_state_from_config()?;
 
         Ok(output)
     }
 
+    pub fn config_path(&self) -> &str {
+        &self.config_path
+    }
+
     pub fn read_config(&self) -> &Config {
         &self.config
     }
@@ -67,11 +232,44 @@ impl ConfigHandler {
 
     pub fn print_state(&self) {
         for (k, v) in self.read_state().as_object().unwrap() {
-            println!("{} : {}", k, v);
+            if is_secret(k) {
+                println!("{} : (hidden)", k);
+            } else {
+                println!("{} : {}", k, v);
+            }
         }
     }
 
-    pub fn set_config(&mut self, new_config: Config) -> Result<(), Box<dyn std::error::Error>> {
+    /// Changes the config, building on the file's contents if it was edited on disk.
+    pub fn update(
+        &mut self,
+        update: impl FnOnce(&mut Config),
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.apply_update(update, true)
+    }
+
+    /// Like [`update`](Self::update), but for bookkeeping such as counters, which `config undo`
+    /// shouldn't step back through.
+    pub fn record(
+        &mut self,
+        update: impl FnOnce(&mut Config),
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.apply_update(update, false)
+    }
+
+    fn apply_update(
+        &mut self,
+        update: impl FnOnce(&mut Config),
+        undoable: bool,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.reload_if_changed()?;
+
+        let mut new_config = self.config.clone();
+        update(&mut new_config);
+
+        if undoable {
+            self.push_history();
+        }
         self.config = new_config;
         self.update_state_from_config()?;
 
@@ -79,17 +277,134 @@ impl ConfigHandler {
         Ok(())
     }
 
-    pub fn set_state(&mut self, new_state: Value) -> Result<(), Box<dyn std::error::Error>> {
+    /// Changes one guild's settings, creating them if the guild had none yet.
+    pub fn update_guild(
+        &mut self,
+        guild_id: GuildId,
+        update: impl FnOnce(&mut GuildConfig),
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.update(|config| update(config.guilds.entry(guild_id).or_default()))
+    }
+
+    /// Sets the value at a dotted path like `guilds.<id>.command_prefix`.
+    ///
+    /// The new state has to deserialize into a [`Config`] before anything is applied. Paths
+    /// that `Config` would ignore are refused unless `force` is set, in which case they are
+    /// kept in the saved file anyway.
+    pub fn set_path(
+        &mut self,
+        path: &str,
+        value: Value,
+        force: bool,
+    ) -> Result<ConfigChange, ConfigError> {
+        self.reload_if_changed()?;
+
+        let keys = path.split('.').collect::<Vec<_>>();
+        let invalid = |reason: String| ConfigError::Invalid {
+            path: path.to_string(),
+            reason,
+        };
+
+        let mut new_state = self.state.clone();
+        let old = set_value_at(&mut new_state, &keys, value.clone()).map_err(invalid)?;
+
+        let mut new_config = Config::deserialize(&new_state).map_err(|e| invalid(e.to_string()))?;
+        self.overrides.apply(&mut new_config);
+
+        if !force {
+            let known = serde_json::to_value(&new_config)
+                .ok()
+                .is_some_and(|config| value_at(&config, &keys).is_some());
+
+            if !known {
+                return Err(ConfigError::UnknownKey(path.to_string()));
+            }
+        }
+
+        self.push_history();
         self.state = new_state;
-        self.update_config_from_state()?;
+        self.config = new_config;
+        self.save_state().map_err(ConfigError::Save)?;
+
+        Ok(ConfigChange {
+            path: path.to_string(),
+            old,
+            new: value,
+        })
+    }
+
+    /// Goes back to the state before the last change. Returns false if there is nothing to undo.
+    pub fn undo(&mut self) -> Result<bool, Box<dyn std::error::Error>> {
+        self.reload_if_changed()?;
+
+        let Some(previous) = self.history.pop() else {
+            return Ok(false);
+        };
+
+        // Counts recorded since then aren't part of the change being undone
+        let edon_count = self.config.edon_count;
 
+        self.state = previous;
+        self.update_config_from_state()?;
+        self.config.edon_count = edon_count;
+        self.update_state_from_config()?;
         self.save_state()?;
 
-        Ok(())
+        Ok(true)
+    }
+
+    /// Whether the file was edited on disk since the last reload attempt.
+    pub fn has_new_edits(&self) -> bool {
+        let modified = self.file_modified();
+
+        modified.is_some() && modified != self.disk_modified && modified != self.rejected_modified
+    }
+
+    /// Loads the file again if it was edited on disk. Edits that don't load are refused, and
+    /// the handler won't save over them until they're fixed.
+    pub fn reload_if_changed(&mut self) -> Result<bool, ConfigError> {
+        let modified = self.file_modified();
+
+        // A deleted file is simply written again on the next save
+        if modified.is_none() || modified == self.disk_modified {
+            return Ok(false);
+        }
+
+        let (state, mut config) = read_config_file(
+            &self.config_path,
+            ConfigFormat::from_path(&self.config_path),
+        )
+        .map_err(|e| {
+            self.rejected_modified = modified;
+            ConfigError::InvalidFile(e.to_string())
+        })?;
+        self.overrides.apply(&mut config);
+
+        self.push_history();
+        self.state = state;
+        self.config = config;
+        self.disk_modified = modified;
+
+        Ok(true)
+    }
+
+    fn file_modified(&self) -> Option<SystemTime> {
+        fs::metadata(&self.config_path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+    }
+
+    fn push_history(&mut self) {
+        if self.history.len() == HISTORY_LENGTH {
+            self.history.remove(0);
+        }
+
+        self.history.push(self.state.clone());
     }
 
     fn update_config_from_state(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         self.config = Config::deserialize(&self.state)?;
+        self.overrides.apply(&mut self.config);
 
         Ok(())
     }
@@ -99,22 +414,381 @@ impl ConfigHandler {
             panic!("Config state is not a map - panic!")
         };
 
-        return if let Value::Object(values_to_add) = serde_json::to_value(&self.config)? {
+        if let Value::Object(values_to_add) = serde_json::to_value(&self.config)? {
+            let overridden = self.overrides.keys();
+
             for (key, value) in values_to_add {
-                map.insert(key, value);
+                // Overridden values stay wherever they came from, e.g. a mounted secret
+                if !overridden.contains(&key.as_str()) {
+                    map.insert(key, value);
+                }
             }
 
             Ok(())
         } else {
             Err(Box::new(serde_json::Error::custom("State is not valid!")))
-        };
+        }
     }
-    pub fn save_state(&self) -> std::io::Result<()> {
-        let mut writer = BufWriter::new(File::create(&self.config_path)?);
-        serde_json::to_writer_pretty(&mut writer, &self.state)?;
+    /// Writes the state to a temporary file and renames it over the config, so a crash mid-write
+    /// can't leave a half written config behind. The file being replaced is kept as the newest of
+    /// [`CONFIG_BACKUPS`] backups.
+    pub fn save_state(&mut self) -> std::io::Result<()> {
+        let modified = self.file_modified();
+        if modified.is_some() && modified != self.disk_modified {
+            return Err(std::io::Error::other(format!(
+                "{} was edited on disk, not saving over it",
+                self.config_path
+            )));
+        }
+
+        let temp_path = format!("{}.tmp", self.config_path);
 
-        writer.flush()?;
+        let previous = fs::read_to_string(&self.config_path).ok();
+        let contents = ConfigFormat::from_path(&self.config_path)
+            .render(&self.state, previous.as_deref())
+            .map_err(|e| std::io::Error::other(e.to_string()))?;
+
+        let mut file = create_like(&temp_path, &self.config_path)?;
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()?;
+
+        if Path::new(&self.config_path).exists() {
+            for i in (1..CONFIG_BACKUPS).rev() {
+                let backup = backup_path(&self.config_path, i);
+
+                if Path::new(&backup).exists() {
+                    fs::rename(&backup, backup_path(&self.config_path, i + 1))?;
+                }
+            }
+
+            fs::copy(&self.config_path, backup_path(&self.config_path, 1))?;
+        }
+
+        fs::rename(&temp_path, &self.config_path)?;
+
+        // The rename only survives a power cut once the directory itself is synced
+        #[cfg(unix)]
+        {
+            let dir = Path::new(&self.config_path)
+                .parent()
+                .filter(|dir| !dir.as_os_str().is_empty())
+                .unwrap_or(Path::new("."));
+            File::open(dir)?.sync_all()?;
+        }
+
+        self.disk_modified = self.file_modified();
 
         Ok(())
     }
 }
+
+/// Reloads the config whenever the file is edited, for as long as the bot runs.
+pub async fn watch_config(data: Arc<RwLock<TypeMap>>) {
+    let mut interval = tokio::time::interval(RELOAD_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        let has_new_edits = {
+            let store = data.read().await;
+            store
+                .get::<ConfigContainer>()
+                .expect("Missing Config")
+                .has_new_edits()
+        };
+
+        if !has_new_edits {
+            continue;
+        }
+
+        let mut store = data.write().await;
+        let config_handler = store.get_mut::<ConfigContainer>().expect("Missing Config");
+
+        match config_handler.reload_if_changed() {
+            Ok(true) => println!("Reloaded config from disk"),
+            Ok(false) => {}
+            Err(e) => println!("{e}"),
+        }
+    }
+}
+
+/// Reads and migrates a config file in `format`. Files from an older version are copied to
+/// `<path>.v<version>` before being migrated.
+fn read_config_file(
+    path: &str,
+    format: ConfigFormat,
+) -> Result<(Value, Config), Box<dyn std::error::Error>> {
+    let mut state = format.parse(&fs::read_to_string(path)?)?;
+
+    let version = config_version(&state)?;
+    if version < CONFIG_VERSION {
+        let backup = format!("{path}.v{version}");
+        fs::copy(path, &backup)?;
+        println!("Backed up {path} to {backup} before migrating it");
+    }
+
+    migrate(&mut state)?;
+
+    let config = Config::deserialize(&state)?;
+
+    Ok((state, config))
+}
+
+/// Creates `path` with the same permissions as `original`, since the config holds secrets such
+/// as the Discord token and may have been locked down. Permissions are set before anything is
+/// written, and only the owner can read the file when there is no `original` yet.
+fn create_like(path: &str, original: &str) -> std::io::Result<File> {
+    let file = File::create(path)?;
+
+    match fs::metadata(original) {
+        Ok(metadata) => file.set_permissions(metadata.permissions())?,
+        #[cfg(unix)]
+        Err(_) => {
+            use std::os::unix::fs::PermissionsExt;
+
+            file.set_permissions(fs::Permissions::from_mode(0o600))?
+        }
+        #[cfg(not(unix))]
+        Err(_) => {}
+    }
+
+    Ok(file)
+}
+
+fn backup_path(config_path: &str, index: usize) -> String {
+    format!("{config_path}.bak.{index}")
+}
+
+/// The value at a path of object keys or array indices.
+pub fn value_at<'a>(value: &'a Value, keys: &[&str]) -> Option<&'a Value> {
+    keys.iter().try_fold(value, |value, key| match value {
+        Value::Object(map) => map.get(*key),
+        Value::Array(items) => key.parse::<usize>().ok().and_then(|i| items.get(i)),
+        _ => None,
+    })
+}
+
+/// Sets the value at a path, creating missing objects along the way, and returns the old value.
+fn set_value_at(root: &mut Value, keys: &[&str], new: Value) -> Result<Option<Value>, String> {
+    let Some((last, parents)) = keys.split_last() else {
+        return Err(String::from("empty path"));
+    };
+
+    if keys.iter().any(|key| key.is_empty()) {
+        return Err(String::from("empty key in path"));
+    }
+
+    let mut value = root;
+    for key in parents {
+        value = match value {
+            Value::Object(map) => map
+                .entry(key.to_string())
+                .or_insert_with(|| Value::Object(Default::default())),
+            Value::Array(items) => key
+                .parse::<usize>()
+                .ok()
+                .and_then(|i| items.get_mut(i))
+                .ok_or_else(|| format!("no item {key}"))?,
+            _ => return Err(format!("`{key}` is inside a value that isn't an object")),
+        };
+    }
+
+    match value {
+        Value::Object(map) => Ok(map.insert(last.to_string(), new)),
+        Value::Array(items) => {
+            let item = last
+                .parse::<usize>()
+                .ok()
+                .and_then(|i| items.get_mut(i))
+                .ok_or_else(|| format!("no item {last}"))?;
+
+            Ok(Some(std::mem::replace(item, new)))
+        }
+        _ => Err(format!("`{last}` is inside a value that isn't an object")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn sets_existing_values() {
+        let mut state = json!({ "command_prefix": "!", "auth_users": [1, 2] });
+
+        let old = set_value_at(&mut state, &["command_prefix"], json!("?")).unwrap();
+        assert_eq!(old, Some(json!("!")));
+
+        let old = set_value_at(&mut state, &["auth_users", "1"], json!(3)).unwrap();
+        assert_eq!(old, Some(json!(2)));
+
+        assert_eq!(
+            state,
+            json!({ "command_prefix": "?", "auth_users": [1, 3] })
+        );
+    }
+
+    #[test]
+    fn creates_missing_objects() {
+        let mut state = json!({ "guilds": {} });
+
+        let old = set_value_at(&mut state, &["guilds", "42", "command_prefix"], json!("$"));
+        assert_eq!(old, Ok(None));
+        assert_eq!(
+            value_at(&state, &["guilds", "42", "command_prefix"]),
+            Some(&json!("$"))
+        );
+    }
+
+    #[test]
+    fn refuses_bad_paths() {
+        let mut state = json!({ "bot_name": "hal", "auth_users": [1] });
+
+        assert!(set_value_at(&mut state, &[], json!(1)).is_err());
+        assert!(set_value_at(&mut state, &["guilds", ""], json!(1)).is_err());
+        assert!(set_value_at(&mut state, &["bot_name", "x"], json!(1)).is_err());
+        assert!(set_value_at(&mut state, &["auth_users", "5"], json!(1)).is_err());
+        assert!(set_value_at(&mut state, &["auth_users", "x"], json!(1)).is_err());
+
+        assert_eq!(state, json!({ "bot_name": "hal", "auth_users": [1] }));
+    }
+
+    #[test]
+    fn redacts_api_keys() {
+        let config = Config {
+            yt_api_key: String::from("yt-secret"),
+            discord_api_key: String::from("discord-secret"),
+            ..Default::default()
+        };
+
+        let logged = format!("{:?}", config.redacted());
+        assert!(!logged.contains("yt-secret"));
+        assert!(!logged.contains("discord-secret"));
+    }
+
+    /// A scratch directory for one test, removed again afterwards.
+    struct TestDir(std::path::PathBuf);
+
+    impl TestDir {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("hal-{}-{name}", std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+
+            TestDir(dir)
+        }
+
+        fn path(&self, file: &str) -> String {
+            self.0.join(file).to_string_lossy().into_owned()
+        }
+    }
+
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn bot_name(path: &str) -> String {
+        let state: Value = serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap();
+        state["bot_name"].as_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn saves_rotate_backups() {
+        let dir = TestDir::new("rotate");
+        let path = dir.path("config.json");
+        fs::write(&path, r#"{ "config_version": 1, "bot_name": "0" }"#).unwrap();
+
+        let mut handler = ConfigHandler::load_config_file(&path, Default::default()).unwrap();
+        for i in 1..=CONFIG_BACKUPS + 2 {
+            handler
+                .update(|config| config.bot_name = i.to_string())
+                .unwrap();
+        }
+
+        assert_eq!(bot_name(&path), (CONFIG_BACKUPS + 2).to_string());
+        for i in 1..=CONFIG_BACKUPS {
+            let backup = backup_path(&path, i);
+            assert_eq!(bot_name(&backup), (CONFIG_BACKUPS + 2 - i).to_string());
+        }
+
+        assert!(!Path::new(&backup_path(&path, CONFIG_BACKUPS + 1)).exists());
+        assert!(!Path::new(&format!("{path}.tmp")).exists());
+    }
+
+    #[test]
+    fn recovers_from_the_newest_backup_that_loads() {
+        let dir = TestDir::new("recover");
+        let path = dir.path("config.json");
+        fs::write(&path, "{ broken").unwrap();
+        fs::write(backup_path(&path, 1), "also broken").unwrap();
+        fs::write(backup_path(&path, 2), r#"{ "bot_name": "two" }"#).unwrap();
+        fs::write(backup_path(&path, 3), r#"{ "bot_name": "three" }"#).unwrap();
+
+        let handler = ConfigHandler::load_config_file(&path, Default::default()).unwrap();
+
+        assert_eq!(handler.read_config().bot_name, "two");
+        assert_eq!(
+            fs::read_to_string(format!("{path}.corrupt")).unwrap(),
+            "{ broken"
+        );
+        assert!(!Path::new(&path).exists());
+    }
+
+    #[test]
+    fn fails_without_anything_to_load() {
+        let dir = TestDir::new("missing");
+        let path = dir.path("config.json");
+
+        assert!(ConfigHandler::load_config_file(&path, Default::default()).is_err());
+        assert!(!Path::new(&format!("{path}.corrupt")).exists());
+    }
+
+    #[test]
+    fn refuses_to_save_over_edits_on_disk() {
+        let dir = TestDir::new("edited");
+        let path = dir.path("config.json");
+        fs::write(&path, r#"{ "bot_name": "old" }"#).unwrap();
+
+        let mut handler = ConfigHandler::load_config_file(&path, Default::default()).unwrap();
+
+        fs::write(&path, r#"{ "bot_name": "edited" }"#).unwrap();
+
+        // Filesystems with coarse timestamps could give the edit the same time as the load
+        let later = SystemTime::now() + std::time::Duration::from_secs(10);
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+
+        assert!(handler.save_state().is_err());
+        assert_eq!(bot_name(&path), "edited");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn saves_keep_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = TestDir::new("permissions");
+        let path = dir.path("config.json");
+        fs::write(&path, r#"{ "bot_name": "hal" }"#).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+
+        let mut handler = ConfigHandler::load_config_file(&path, Default::default()).unwrap();
+        handler
+            .update(|config| config.bot_name = String::from("hal 2"))
+            .unwrap();
+
+        let mode = |path: &str| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(&path), 0o640);
+
+        let new = dir.path("new.json");
+        create_like(&new, &dir.path("nothing.json")).unwrap();
+        assert_eq!(mode(&new), 0o600);
+    }
+}
@@ -0,0 +1,197 @@
+use serde::de::IntoDeserializer;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use toml_edit::{DocumentMut, Item, Table};
+
+use std::error::Error;
+use std::path::Path;
+
+/// File format of the config, picked from the extension of its path. Anything that isn't
+/// `.toml`, `.yaml` or `.yml` is JSON.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ConfigFormat {
+    #[default]
+    Json,
+    /// Comments and layout are kept when the file is saved.
+    Toml,
+    /// Comments are lost when the file is saved, serde_yaml can't keep them.
+    Yaml,
+}
+
+impl ConfigFormat {
+    pub fn from_path(path: &str) -> Self {
+        match Path::new(path).extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => ConfigFormat::Toml,
+            Some("yaml" | "yml") => ConfigFormat::Yaml,
+            _ => ConfigFormat::Json,
+        }
+    }
+
+    pub fn parse(self, text: &str) -> Result<Value, Box<dyn Error>> {
+        Ok(match self {
+            ConfigFormat::Json => serde_json::from_str(text)?,
+            ConfigFormat::Toml => toml_edit::de::from_str(text)?,
+            ConfigFormat::Yaml => serde_yaml::from_str(text)?,
+        })
+    }
+
+    /// Renders the state as a file. For TOML, the `previous` file is edited in place so its
+    /// comments and formatting survive.
+    pub fn render(self, state: &Value, previous: Option<&str>) -> Result<String, Box<dyn Error>> {
+        Ok(match self {
+            ConfigFormat::Json => serde_json::to_string_pretty(state)?,
+            ConfigFormat::Yaml => serde_yaml::to_string(state)?,
+            ConfigFormat::Toml => {
+                let Value::Object(map) = state else {
+                    return Err("config isn't a map".into());
+                };
+
+                let mut document = previous
+                    .and_then(|previous| previous.parse::<DocumentMut>().ok())
+                    .unwrap_or_default();
+                merge_table(document.as_table_mut(), map)?;
+
+                document.to_string()
+            }
+        })
+    }
+}
+
+/// Makes `table` hold the values in `map`, only touching entries that changed. TOML has no
+/// null, so null values are left out like any other unset key.
+fn merge_table(table: &mut Table, map: &Map<String, Value>) -> Result<(), Box<dyn Error>> {
+    table.retain(|key, _| map.get(key).is_some_and(|value| !value.is_null()));
+
+    for (key, value) in map {
+        if value.is_null() {
+            continue;
+        }
+
+        match (table.get_mut(key), value) {
+            (Some(Item::Table(inner)), Value::Object(inner_map)) => merge_table(inner, inner_map)?,
+            (Some(Item::Value(old)), _) => {
+                if Value::deserialize(old.clone().into_deserializer())
+                    .ok()
+                    .as_ref()
+                    != Some(value)
+                {
+                    let decor = old.decor().clone();
+                    *old = to_item(key, value)?
+                        .into_value()
+                        .map_err(|_| format!("`{key}` can't be written inline"))?;
+                    *old.decor_mut() = decor;
+                }
+            }
+            _ => {
+                table.insert(key, to_item(key, value)?);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn to_item(key: &str, value: &Value) -> Result<Item, Box<dyn Error>> {
+    let mut wrapper = Map::new();
+    wrapper.insert(key.to_string(), without_nulls(value));
+
+    let mut document = toml_edit::ser::to_document(&wrapper)?;
+    document
+        .remove(key)
+        .ok_or_else(|| format!("`{key}` couldn't be written as TOML").into())
+}
+
+fn without_nulls(value: &Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .filter(|(_, value)| !value.is_null())
+                .map(|(key, value)| (key.clone(), without_nulls(value)))
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(items.iter().map(without_nulls).collect()),
+        _ => value.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const COMMENTED: &str = r#"# Bot settings
+bot_name = "hal" # shown in help
+command_prefix = "!"
+
+# Per server
+[guilds.42]
+# Volume in percent
+default_volume = 80
+"#;
+
+    fn round_trip(state: Value) -> String {
+        ConfigFormat::Toml.render(&state, Some(COMMENTED)).unwrap()
+    }
+
+    #[test]
+    fn picks_format_from_extension() {
+        assert_eq!(ConfigFormat::from_path("config.toml"), ConfigFormat::Toml);
+        assert_eq!(ConfigFormat::from_path("a/config.yml"), ConfigFormat::Yaml);
+        assert_eq!(ConfigFormat::from_path("config.yaml"), ConfigFormat::Yaml);
+        assert_eq!(ConfigFormat::from_path("config.json"), ConfigFormat::Json);
+        assert_eq!(ConfigFormat::from_path("config"), ConfigFormat::Json);
+    }
+
+    #[test]
+    fn unchanged_toml_is_kept_exactly() {
+        let state = ConfigFormat::Toml.parse(COMMENTED).unwrap();
+
+        assert_eq!(round_trip(state), COMMENTED);
+    }
+
+    #[test]
+    fn changes_keep_comments() {
+        let rendered = round_trip(json!({
+            "bot_name": "hal 2",
+            "command_prefix": "!",
+            "guilds": { "42": { "default_volume": 50 } },
+        }));
+
+        assert!(rendered.contains("# Bot settings"));
+        assert!(rendered.contains(r#"bot_name = "hal 2" # shown in help"#));
+        assert!(rendered.contains("# Volume in percent\ndefault_volume = 50"));
+    }
+
+    #[test]
+    fn adds_and_removes_keys() {
+        let rendered = round_trip(json!({
+            "bot_name": "hal",
+            "edon_count": 3,
+            "max_queue_length": null,
+            "guilds": { "42": { "default_volume": 80 }, "7": { "command_prefix": "?" } },
+        }));
+
+        assert!(!rendered.contains("command_prefix = \"!\""));
+        assert!(!rendered.contains("max_queue_length"));
+        assert!(rendered.contains("edon_count = 3"));
+
+        assert_eq!(
+            ConfigFormat::Toml.parse(&rendered).unwrap(),
+            json!({
+                "bot_name": "hal",
+                "edon_count": 3,
+                "guilds": { "42": { "default_volume": 80 }, "7": { "command_prefix": "?" } },
+            })
+        );
+    }
+
+    #[test]
+    fn formats_parse_what_they_render() {
+        let state = json!({ "bot_name": "hal", "auth_users": [1, 2], "guilds": {} });
+
+        for format in [ConfigFormat::Json, ConfigFormat::Toml, ConfigFormat::Yaml] {
+            let rendered = format.render(&state, None).unwrap();
+            assert_eq!(format.parse(&rendered).unwrap(), state, "{format:?}");
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use serde_json::{Map, Value};
+
+/// The config layout this build reads and writes.
+pub const CONFIG_VERSION: u32 = 1;
+
+/// One step in the chain, taking a config from one version to the next.
+struct Migration {
+    /// What the step does, for the log.
+    description: &'static str,
+    /// Changes the config in place, returning a line for each thing it changed.
+    migrate: fn(&mut Map<String, Value>) -> Vec<String>,
+}
+
+/// `MIGRATIONS[i]` takes a config from version `i` to `i + 1`. Files from before versioning
+/// are version 0.
+///
+/// Steps should rename or move keys rather than drop them, so nothing is lost if a field is
+/// removed from `Config` by mistake.
+const MIGRATIONS: [Migration; CONFIG_VERSION as usize] = [Migration {
+    description: "Start tracking config_version",
+    migrate: |_| Vec::new(),
+}];
+
+/// The version a config was written by.
+pub fn config_version(state: &Value) -> Result<u32, String> {
+    match state.get("config_version") {
+        None => Ok(0),
+        Some(version) => version
+            .as_u64()
+            .and_then(|version| u32::try_from(version).ok())
+            .ok_or_else(|| format!("config_version {version} isn't a version number")),
+    }
+}
+
+/// Brings a config up to [`CONFIG_VERSION`], logging every change. Callers should back the
+/// file up first.
+pub fn migrate(state: &mut Value) -> Result<(), String> {
+    let version = config_version(state)?;
+
+    if version > CONFIG_VERSION {
+        return Err(format!(
+            "config_version {version} is newer than this build supports ({CONFIG_VERSION})"
+        ));
+    }
+
+    let Value::Object(map) = state else {
+        return Err(String::from("config isn't a map"));
+    };
+
+    for (from, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        println!(
+            "Migrating config from version {} to {}: {}",
+            from,
+            from + 1,
+            migration.description
+        );
+
+        for change in (migration.migrate)(map) {
+            println!("  {change}");
+        }
+
+        map.insert(String::from("config_version"), Value::from(from + 1));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn reads_versions() {
+        assert_eq!(config_version(&json!({})), Ok(0));
+        assert_eq!(config_version(&json!({ "config_version": 1 })), Ok(1));
+        assert!(config_version(&json!({ "config_version": "1" })).is_err());
+        assert!(config_version(&json!({ "config_version": -1 })).is_err());
+    }
+
+    #[test]
+    fn migrates_unversioned_configs() {
+        let mut state = json!({ "bot_name": "hal" });
+
+        migrate(&mut state).unwrap();
+
+        assert_eq!(
+            state,
+            json!({ "bot_name": "hal", "config_version": CONFIG_VERSION })
+        );
+    }
+
+    #[test]
+    fn leaves_current_configs_alone() {
+        let mut state = json!({ "bot_name": "hal", "config_version": CONFIG_VERSION });
+        let before = state.clone();
+
+        migrate(&mut state).unwrap();
+
+        assert_eq!(state, before);
+    }
+
+    #[test]
+    fn refuses_newer_configs() {
+        let mut state = json!({ "config_version": CONFIG_VERSION + 1 });
+
+        assert!(migrate(&mut state).is_err());
+        assert_eq!(state, json!({ "config_version": CONFIG_VERSION + 1 }));
+    }
+
+    #[test]
+    fn refuses_non_maps() {
+        assert!(migrate(&mut json!([1, 2])).is_err());
+    }
+}
@@ -0,0 +1,126 @@
+use serenity::all::{GuildId, UserId};
+
+use std::collections::HashMap;
+
+use super::config::Config;
+use super::guild_config::GuildConfig;
+
+/// Config values given on the command line or in `HAL_*` environment variables.
+///
+/// These win over `config.json` (and a flag wins over its variable), but are never written
+/// back to it, so secrets like the Discord token can live outside the file entirely.
+#[derive(clap::Args, Debug, Clone, Default)]
+#[command(about = None, next_help_heading = "Config overrides")]
+pub struct ConfigOverrides {
+    #[arg(long, env = "HAL_BOT_NAME")]
+    pub bot_name: Option<String>,
+    #[arg(long, env = "HAL_COMMAND_PREFIX")]
+    pub command_prefix: Option<String>,
+    /// Bot owners, separated by commas.
+    #[arg(long, env = "HAL_AUTH_USERS", value_delimiter = ',')]
+    pub auth_users: Option<Vec<UserId>>,
+    #[arg(long, env = "HAL_YT_API_KEY", hide_env_values = true)]
+    pub yt_api_key: Option<String>,
+    #[arg(long, env = "HAL_DISCORD_API_KEY", hide_env_values = true)]
+    pub discord_api_key: Option<String>,
+    #[arg(long, env = "HAL_EDON_COUNT")]
+    pub edon_count: Option<usize>,
+    /// Guilds to register slash commands in, separated by commas.
+    #[arg(long, env = "HAL_SLASH_COMMAND_GUILDS", value_delimiter = ',')]
+    pub slash_command_guilds: Option<Vec<GuildId>>,
+    #[arg(long, env = "HAL_DEFAULT_VOLUME")]
+    pub default_volume: Option<u32>,
+    #[arg(long, env = "HAL_MAX_VOLUME")]
+    pub max_volume: Option<u32>,
+    #[arg(long, env = "HAL_SKIP_VOTE_PERCENT")]
+    pub skip_vote_percent: Option<u32>,
+    #[arg(long, env = "HAL_MAX_QUEUE_LENGTH")]
+    pub max_queue_length: Option<usize>,
+    #[arg(long, env = "HAL_MAX_TRACKS_PER_USER")]
+    pub max_tracks_per_user: Option<usize>,
+    /// In seconds.
+    #[arg(long, env = "HAL_MAX_TRACK_LENGTH")]
+    pub max_track_length: Option<u64>,
+    #[arg(long, env = "HAL_MAX_PLAYLIST_LENGTH")]
+    pub max_playlist_length: Option<usize>,
+    /// In seconds.
+    #[arg(long, env = "HAL_IDLE_TIMEOUT")]
+    pub idle_timeout: Option<u64>,
+    /// Per-guild settings as JSON, replacing the ones in the file.
+    #[arg(long, env = "HAL_GUILDS", value_parser = parse_guilds)]
+    pub guilds: Option<HashMap<GuildId, GuildConfig>>,
+}
+
+impl ConfigOverrides {
+    pub fn apply(&self, config: &mut Config) {
+        macro_rules! apply {
+            ($($field:ident),*) => {
+                $(
+                    if let Some(value) = &self.$field {
+                        config.$field = value.clone();
+                    }
+                )*
+            };
+        }
+
+        apply!(
+            bot_name,
+            command_prefix,
+            auth_users,
+            yt_api_key,
+            discord_api_key,
+            edon_count,
+            slash_command_guilds,
+            default_volume,
+            max_volume,
+            skip_vote_percent,
+            idle_timeout,
+            guilds
+        );
+
+        if let Some(max) = self.max_queue_length {
+            config.max_queue_length = Some(max);
+        }
+        if let Some(max) = self.max_tracks_per_user {
+            config.max_tracks_per_user = Some(max);
+        }
+        if let Some(max) = self.max_track_length {
+            config.max_track_length = Some(max);
+        }
+        if let Some(max) = self.max_playlist_length {
+            config.max_playlist_length = Some(max);
+        }
+    }
+
+    /// Names of the `Config` fields that are overridden, as they appear in the config file.
+    pub fn keys(&self) -> Vec<&'static str> {
+        let overridden = [
+            ("bot_name", self.bot_name.is_some()),
+            ("command_prefix", self.command_prefix.is_some()),
+            ("auth_users", self.auth_users.is_some()),
+            ("yt_api_key", self.yt_api_key.is_some()),
+            ("discord_api_key", self.discord_api_key.is_some()),
+            ("edon_count", self.edon_count.is_some()),
+            ("slash_command_guilds", self.slash_command_guilds.is_some()),
+            ("default_volume", self.default_volume.is_some()),
+            ("max_volume", self.max_volume.is_some()),
+            ("skip_vote_percent", self.skip_vote_percent.is_some()),
+            ("max_queue_length", self.max_queue_length.is_some()),
+            ("max_tracks_per_user", self.max_tracks_per_user.is_some()),
+            ("max_track_length", self.max_track_length.is_some()),
+            ("max_playlist_length", self.max_playlist_length.is_some()),
+            ("idle_timeout", self.idle_timeout.is_some()),
+            ("guilds", self.guilds.is_some()),
+        ];
+
+        overridden
+            .into_iter()
+            .filter(|(_, set)| *set)
+            .map(|(key, _)| key)
+            .collect()
+    }
+}
+
+fn parse_guilds(json: &str) -> Result<HashMap<GuildId, GuildConfig>, serde_json::Error> {
+    serde_json::from_str(json)
+}
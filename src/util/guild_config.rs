@@ -0,0 +1,231 @@
+use serde::{Deserialize, Serialize};
+use serenity::all::{ChannelId, GuildId, RoleId, UserId};
+use serenity::client::Context;
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use super::config::Config;
+use super::permissions::Permission;
+use crate::ConfigContainer;
+
+/// The loudest any volume setting can go, in percent.
+pub const VOLUME_LIMIT: u32 = 200;
+
+/// Settings for a single guild. Anything left unset falls back to the global [`Config`].
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct GuildConfig {
+    pub command_prefix: Option<String>,
+    /// Members with this role are DJs, on top of anything granted.
+    pub dj_role: Option<RoleId>,
+    /// Volume tracks play at, in percent. Changed by `volume`.
+    pub default_volume: Option<u32>,
+    /// Highest volume `volume` allows, in percent.
+    pub max_volume: Option<u32>,
+    /// Share of listeners, in percent, that have to vote before `skip` skips.
+    pub skip_vote_percent: Option<u32>,
+    pub max_queue_length: Option<usize>,
+    /// How many songs one person can have queued at once.
+    pub max_tracks_per_user: Option<usize>,
+    /// Longest song that can be queued, in seconds.
+    pub max_track_length: Option<u64>,
+    /// How many songs of a playlist are queued at most.
+    pub max_playlist_length: Option<usize>,
+    /// Only DJs may change the queue.
+    pub dj_only: bool,
+    /// How long, in seconds, the bot stays in a voice channel with nothing to do.
+    pub idle_timeout: Option<u64>,
+    /// Never leave a voice channel for being idle.
+    pub always_on: bool,
+    /// Text channels music commands may be used in. Empty allows all of them.
+    pub music_channels: Vec<ChannelId>,
+    /// Where the bot posts things nobody asked for directly, like leaving an idle voice channel.
+    /// Otherwise they go wherever music commands were last used.
+    pub announce_channel: Option<ChannelId>,
+    pub user_permissions: HashMap<UserId, Permission>,
+    pub role_permissions: HashMap<RoleId, Permission>,
+}
+
+impl GuildConfig {
+    pub fn grants_dj(&self) -> bool {
+        self.dj_role.is_some()
+            || self
+                .user_permissions
+                .values()
+                .chain(self.role_permissions.values())
+                .any(|level| *level == Permission::Dj)
+    }
+}
+
+/// A guild's settings with the global fallbacks already applied.
+#[derive(Debug, Clone)]
+pub struct GuildSettings {
+    pub command_prefix: String,
+    pub dj_role: Option<RoleId>,
+    /// Never above `max_volume`.
+    pub default_volume: u32,
+    pub max_volume: u32,
+    pub skip_vote_percent: u32,
+    pub max_queue_length: Option<usize>,
+    pub max_tracks_per_user: Option<usize>,
+    pub max_track_length: Option<Duration>,
+    pub max_playlist_length: Option<usize>,
+    pub dj_only: bool,
+    pub idle_timeout: Duration,
+    pub always_on: bool,
+    pub music_channels: Vec<ChannelId>,
+    pub announce_channel: Option<ChannelId>,
+}
+
+impl GuildSettings {
+    pub fn allows_music_in(&self, channel_id: ChannelId) -> bool {
+        self.music_channels.is_empty() || self.music_channels.contains(&channel_id)
+    }
+}
+
+impl Config {
+    /// Settings for `guild_id`, or the global ones outside of a guild.
+    pub fn guild_settings(&self, guild_id: Option<GuildId>) -> GuildSettings {
+        let guild = guild_id
+            .and_then(|guild_id| self.guilds.get(&guild_id))
+            .cloned()
+            .unwrap_or_default();
+
+        let max_volume = guild.max_volume.unwrap_or(self.max_volume);
+
+        GuildSettings {
+            command_prefix: guild
+                .command_prefix
+                .unwrap_or_else(|| self.command_prefix.clone()),
+            dj_role: guild.dj_role,
+            // Lowering the maximum also turns down servers already above it
+            default_volume: guild
+                .default_volume
+                .unwrap_or(self.default_volume)
+                .min(max_volume),
+            max_volume,
+            skip_vote_percent: guild.skip_vote_percent.unwrap_or(self.skip_vote_percent),
+            max_queue_length: guild.max_queue_length.or(self.max_queue_length),
+            max_tracks_per_user: guild.max_tracks_per_user.or(self.max_tracks_per_user),
+            max_track_length: guild
+                .max_track_length
+                .or(self.max_track_length)
+                .map(Duration::from_secs),
+            max_playlist_length: guild.max_playlist_length.or(self.max_playlist_length),
+            dj_only: guild.dj_only,
+            idle_timeout: Duration::from_secs(guild.idle_timeout.unwrap_or(self.idle_timeout)),
+            always_on: guild.always_on,
+            music_channels: guild.music_channels,
+            announce_channel: guild.announce_channel,
+        }
+    }
+}
+
+pub async fn guild_settings(ctx: &Context, guild_id: Option<GuildId>) -> GuildSettings {
+    let store = ctx.data.read().await;
+
+    store
+        .get::<ConfigContainer>()
+        .expect("Missing Config")
+        .read_config()
+        .guild_settings(guild_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GUILD: GuildId = GuildId::new(42);
+
+    fn config_with(guild: GuildConfig) -> Config {
+        let mut config = Config {
+            command_prefix: String::from("!"),
+            default_volume: 100,
+            max_volume: 200,
+            max_queue_length: Some(50),
+            max_track_length: Some(600),
+            idle_timeout: 300,
+            ..Default::default()
+        };
+        config.guilds.insert(GUILD, guild);
+
+        config
+    }
+
+    #[test]
+    fn falls_back_to_global_settings() {
+        let settings = config_with(GuildConfig::default()).guild_settings(Some(GUILD));
+
+        assert_eq!(settings.command_prefix, "!");
+        assert_eq!(settings.default_volume, 100);
+        assert_eq!(settings.max_queue_length, Some(50));
+        assert_eq!(settings.max_track_length, Some(Duration::from_secs(600)));
+        assert_eq!(settings.idle_timeout, Duration::from_secs(300));
+        assert!(!settings.dj_only);
+    }
+
+    #[test]
+    fn guild_settings_win() {
+        let config = config_with(GuildConfig {
+            command_prefix: Some(String::from("?")),
+            default_volume: Some(80),
+            max_queue_length: Some(10),
+            max_tracks_per_user: Some(3),
+            idle_timeout: Some(60),
+            dj_only: true,
+            ..Default::default()
+        });
+        let settings = config.guild_settings(Some(GUILD));
+
+        assert_eq!(settings.command_prefix, "?");
+        assert_eq!(settings.default_volume, 80);
+        assert_eq!(settings.max_queue_length, Some(10));
+        assert_eq!(settings.max_tracks_per_user, Some(3));
+        assert_eq!(settings.idle_timeout, Duration::from_secs(60));
+        assert!(settings.dj_only);
+    }
+
+    #[test]
+    fn volume_is_capped_at_the_maximum() {
+        let config = config_with(GuildConfig {
+            default_volume: Some(150),
+            max_volume: Some(120),
+            ..Default::default()
+        });
+        assert_eq!(config.guild_settings(Some(GUILD)).default_volume, 120);
+
+        let mut config = config_with(GuildConfig {
+            max_volume: Some(50),
+            ..Default::default()
+        });
+        assert_eq!(config.guild_settings(Some(GUILD)).default_volume, 50);
+
+        config.max_volume = 80;
+        assert_eq!(config.guild_settings(None).default_volume, 80);
+    }
+
+    #[test]
+    fn other_guilds_and_dms_use_global_settings() {
+        let config = config_with(GuildConfig {
+            command_prefix: Some(String::from("?")),
+            ..Default::default()
+        });
+
+        assert_eq!(config.guild_settings(None).command_prefix, "!");
+        assert_eq!(
+            config.guild_settings(Some(GuildId::new(7))).command_prefix,
+            "!"
+        );
+    }
+
+    #[test]
+    fn music_channels_limit_music_commands() {
+        let mut settings = config_with(GuildConfig::default()).guild_settings(Some(GUILD));
+        assert!(settings.allows_music_in(ChannelId::new(1)));
+
+        settings.music_channels = vec![ChannelId::new(2)];
+        assert!(!settings.allows_music_in(ChannelId::new(1)));
+        assert!(settings.allows_music_in(ChannelId::new(2)));
+    }
+}
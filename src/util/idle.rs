@@ -0,0 +1,112 @@
+use serenity::all::{Cache, ChannelId, GuildId, Http};
+use serenity::prelude::TypeMap;
+use songbird::serenity::SongbirdKey;
+use songbird::tracks::PlayMode;
+use songbird::Call;
+use tokio::sync::{Mutex, RwLock};
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::commands::args::format_duration;
+use crate::commands::music_util::listeners;
+use crate::{ConfigContainer, PlaybackContainer};
+
+/// How often voice calls are checked for having nothing to do.
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Leaves voice channels where the queue is empty or paused, or nobody is listening, for longer
+/// than the guild's idle timeout. Anything else going on starts the timeout over.
+pub async fn watch_idle(data: Arc<RwLock<TypeMap>>, cache: Arc<Cache>, http: Arc<Http>) {
+    let mut interval = tokio::time::interval(IDLE_CHECK_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        let (songbird, playback) = {
+            let store = data.read().await;
+            (
+                store
+                    .get::<SongbirdKey>()
+                    .cloned()
+                    .expect("Missing songbird"),
+                store
+                    .get::<PlaybackContainer>()
+                    .cloned()
+                    .expect("Missing playback states"),
+            )
+        };
+
+        // The iterator holds locks on songbird's map, so don't keep it across awaits
+        let calls = songbird.iter().collect::<Vec<_>>();
+
+        for (guild_id, call) in calls {
+            let guild_id = GuildId::from(guild_id.0);
+
+            let settings = data
+                .read()
+                .await
+                .get::<ConfigContainer>()
+                .expect("Missing Config")
+                .read_config()
+                .guild_settings(Some(guild_id));
+
+            let idle = !settings.always_on && is_idle(&cache, guild_id, &call).await;
+
+            let text_channel = {
+                let mut playback = playback.lock().await;
+                let playback = playback.entry(guild_id).or_default();
+
+                if !idle {
+                    playback.idle_since = None;
+                    continue;
+                }
+
+                let since = *playback.idle_since.get_or_insert_with(Instant::now);
+                if since.elapsed() < settings.idle_timeout {
+                    continue;
+                }
+
+                playback.idle_since = None;
+                settings.announce_channel.or(playback.text_channel)
+            };
+
+            call.lock().await.queue().stop();
+            if let Err(e) = songbird.remove(guild_id).await {
+                println!("Failed to leave {guild_id}: {e}");
+                continue;
+            }
+
+            if let Some(text_channel) = text_channel {
+                let message = format!(
+                    "Left the voice channel after {} with nothing to do",
+                    format_duration(settings.idle_timeout)
+                );
+                crate::check_msg(text_channel.say(&http, message).await);
+            }
+        }
+    }
+}
+
+/// Whether a call has nothing playing, or nobody to play to.
+async fn is_idle(cache: &Cache, guild_id: GuildId, call: &Mutex<Call>) -> bool {
+    let call = call.lock().await;
+
+    let Some(channel) = call.current_channel() else {
+        return false;
+    };
+
+    let alone = listeners(cache, guild_id, ChannelId::from(channel.0))
+        .is_some_and(|listeners| listeners.is_empty());
+    if alone {
+        return true;
+    }
+
+    match call.queue().current() {
+        Some(current) => current
+            .get_info()
+            .await
+            .map_or(true, |state| state.playing == PlayMode::Pause),
+        None => true,
+    }
+}
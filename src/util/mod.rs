@@ -1,2 +1,11 @@
 pub mod config;
-pub mod typemap;
\ No newline at end of file
+pub mod config_format;
+pub mod config_migrations;
+pub mod config_overrides;
+pub mod guild_config;
+pub mod idle;
+pub mod permissions;
+pub mod playback;
+pub mod saved_queues;
+pub mod stage;
+pub mod typemap;
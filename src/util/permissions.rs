@@ -0,0 +1,191 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use serenity::all::{GuildId, RoleId, UserId};
+use serenity::client::Context;
+
+use crate::commands::args::FromArg;
+use crate::ConfigContainer;
+
+/// How trusted a user is. Each level can do everything the levels below it can.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Permission {
+    Everyone,
+    /// Can moderate music playback.
+    Dj,
+    /// Can manage the bot within a guild.
+    Admin,
+    /// Runs the bot itself, see `Config::auth_users`.
+    Owner,
+}
+
+impl fmt::Display for Permission {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Permission::Everyone => "Everyone",
+            Permission::Dj => "DJ",
+            Permission::Admin => "Admin",
+            Permission::Owner => "Owner",
+        };
+
+        f.write_str(name)
+    }
+}
+
+impl FromArg for Permission {
+    const NAME: &'static str = "permission level (everyone, dj, admin)";
+
+    fn from_arg(arg: &str) -> Option<Self> {
+        // Owners come from the global config, they can't be granted per guild
+        match arg.to_lowercase().as_str() {
+            "everyone" => Some(Permission::Everyone),
+            "dj" => Some(Permission::Dj),
+            "admin" => Some(Permission::Admin),
+            _ => None,
+        }
+    }
+}
+
+/// Either side of a permission grant.
+#[derive(Clone, Copy, Debug)]
+pub enum Grantee {
+    User(UserId),
+    Role(RoleId),
+}
+
+impl FromArg for Grantee {
+    const NAME: &'static str = "user or role";
+
+    fn from_arg(arg: &str) -> Option<Self> {
+        if arg.starts_with("<@&") {
+            RoleId::from_arg(arg).map(Grantee::Role)
+        } else {
+            UserId::from_arg(arg).map(Grantee::User)
+        }
+    }
+}
+
+impl fmt::Display for Grantee {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Grantee::User(id) => write!(f, "<@{id}>"),
+            Grantee::Role(id) => write!(f, "<@&{id}>"),
+        }
+    }
+}
+
+/// Works out a user's level in a guild.
+///
+/// Owners are global. Otherwise the guild owner and members with Administrator or Manage Server
+/// are admins, and anything granted to the user or one of their roles applies, as does the
+/// guild's DJ role. A guild that hasn't granted DJ to anyone treats everyone as a DJ.
+pub async fn permission_level(
+    ctx: &Context,
+    guild_id: Option<GuildId>,
+    user_id: UserId,
+) -> Permission {
+    resolve_level(ctx, guild_id, user_id, true).await
+}
+
+/// Whether a user is a DJ through a grant, the DJ role, or being an admin or owner, ignoring
+/// that everyone counts as a DJ while nobody has been granted it. For things that only mean
+/// something when some users aren't DJs, like skipping without a vote.
+pub async fn is_explicit_dj(ctx: &Context, guild_id: Option<GuildId>, user_id: UserId) -> bool {
+    resolve_level(ctx, guild_id, user_id, false).await >= Permission::Dj
+}
+
+/// `permission_level`, with the everyone-is-a-DJ fallback only applied if `open_dj` is set.
+async fn resolve_level(
+    ctx: &Context,
+    guild_id: Option<GuildId>,
+    user_id: UserId,
+    open_dj: bool,
+) -> Permission {
+    let (is_owner, grants) = {
+        let store = ctx.data.read().await;
+        let config = store
+            .get::<ConfigContainer>()
+            .expect("Missing Config")
+            .read_config();
+
+        (
+            config.auth_users.contains(&user_id),
+            guild_id.and_then(|guild_id| config.guilds.get(&guild_id).cloned()),
+        )
+    };
+
+    if is_owner {
+        return Permission::Owner;
+    }
+
+    let Some(guild_id) = guild_id else {
+        return Permission::Everyone;
+    };
+
+    let cached_member = ctx
+        .cache
+        .guild(guild_id)
+        .and_then(|guild| guild.members.get(&user_id).cloned());
+
+    let member = match cached_member {
+        Some(member) => Some(member),
+        None => guild_id.member(ctx, user_id).await.ok(),
+    };
+
+    let mut level = Permission::Everyone;
+
+    if let Some(guild) = ctx.cache.guild(guild_id) {
+        let discord_admin = match &member {
+            Some(member) => {
+                let permissions = guild.member_permissions(member);
+                permissions.administrator() || permissions.manage_guild()
+            }
+            None => false,
+        };
+
+        if guild.owner_id == user_id || discord_admin {
+            level = Permission::Admin;
+        }
+    }
+
+    let Some(grants) = grants else {
+        return if open_dj {
+            level.max(Permission::Dj)
+        } else {
+            level
+        };
+    };
+
+    if let Some(granted) = grants.user_permissions.get(&user_id) {
+        level = level.max(*granted);
+    }
+
+    if let Some(member) = &member {
+        for role in &member.roles {
+            if let Some(granted) = grants.role_permissions.get(role) {
+                level = level.max(*granted);
+            }
+
+            if grants.dj_role == Some(*role) {
+                level = level.max(Permission::Dj);
+            }
+        }
+    }
+
+    if open_dj && !grants.grants_dj() {
+        level = level.max(Permission::Dj);
+    }
+
+    level
+}
+
+pub async fn has_permission(
+    ctx: &Context,
+    guild_id: Option<GuildId>,
+    user_id: UserId,
+    permission: Permission,
+) -> bool {
+    permission == Permission::Everyone
+        || permission_level(ctx, guild_id, user_id).await >= permission
+}
@@ -0,0 +1,111 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::sync::Arc;
+use std::time::Instant;
+
+use serenity::all::{ChannelId, GuildId, MessageId, UserId};
+use serenity::client::Context;
+use songbird::tracks::TrackHandle;
+use tokio::sync::Mutex;
+
+use crate::commands::args::FromArg;
+use crate::commands::music_util::TrackInfo;
+use crate::PlaybackContainer;
+
+/// What happens when a track finishes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LoopMode {
+    #[default]
+    Off,
+    /// The current track repeats until it's skipped.
+    Track,
+    /// Finished tracks go back on the end of the queue.
+    Queue,
+}
+
+impl fmt::Display for LoopMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            LoopMode::Off => "off",
+            LoopMode::Track => "track",
+            LoopMode::Queue => "queue",
+        };
+
+        f.write_str(name)
+    }
+}
+
+impl FromArg for LoopMode {
+    const NAME: &'static str = "loop mode (off, track, queue)";
+
+    fn from_arg(arg: &str) -> Option<Self> {
+        match arg.to_lowercase().as_str() {
+            "off" => Some(LoopMode::Off),
+            "track" | "song" => Some(LoopMode::Track),
+            "queue" | "all" => Some(LoopMode::Queue),
+            _ => None,
+        }
+    }
+}
+
+/// How many finished tracks `history` remembers per guild.
+pub const HISTORY_LENGTH: usize = 20;
+
+/// Playback state for one guild that only lives as long as the bot runs.
+#[derive(Debug, Default)]
+pub struct GuildPlayback {
+    pub loop_mode: LoopMode,
+    /// The `nowplaying` message that's kept up to date, older ones stop updating.
+    pub now_playing: Option<MessageId>,
+    /// Finished tracks, most recent first.
+    pub history: VecDeque<TrackInfo>,
+    pub skip_votes: SkipVotes,
+    /// Where music commands were last used, for messages nobody asked for.
+    pub text_channel: Option<ChannelId>,
+    /// When the bot last had nothing to do, see `idle`.
+    pub idle_since: Option<Instant>,
+}
+
+/// Who has voted to skip a track. Votes for any other track don't count.
+#[derive(Debug, Default)]
+pub struct SkipVotes {
+    pub track: Option<TrackHandle>,
+    pub voters: HashSet<UserId>,
+}
+
+impl SkipVotes {
+    /// Adds a vote for `track`, dropping the votes if they were for an earlier one.
+    pub fn vote(&mut self, track: &TrackHandle, user_id: UserId) {
+        if self.track.as_ref().map(TrackHandle::uuid) != Some(track.uuid()) {
+            self.track = Some(track.clone());
+            self.voters.clear();
+        }
+
+        self.voters.insert(user_id);
+    }
+}
+
+pub type PlaybackStates = Arc<Mutex<HashMap<GuildId, GuildPlayback>>>;
+
+pub async fn playback_states(ctx: &Context) -> PlaybackStates {
+    ctx.data
+        .read()
+        .await
+        .get::<PlaybackContainer>()
+        .cloned()
+        .expect("Missing playback states")
+}
+
+pub async fn loop_mode(ctx: &Context, guild_id: Option<GuildId>) -> LoopMode {
+    let Some(guild_id) = guild_id else {
+        return LoopMode::Off;
+    };
+
+    playback_states(ctx)
+        .await
+        .lock()
+        .await
+        .get(&guild_id)
+        .map(|playback| playback.loop_mode)
+        .unwrap_or_default()
+}
@@ -0,0 +1,256 @@
+use serde::{Deserialize, Serialize};
+use serenity::all::{ChannelId, GuildId, Timestamp, UserId};
+use serenity::client::Context;
+use serenity::prelude::TypeMap;
+use songbird::input::AuxMetadata;
+use songbird::serenity::SongbirdKey;
+use tokio::sync::RwLock;
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::guild_config::guild_settings;
+use super::stage::take_stage;
+use crate::commands::music::{add_call_events, volume_scale};
+use crate::commands::music_util::{enqueue, track_info, TrackInfo};
+use crate::commands::ytdl::Ytdl;
+use crate::{ConfigContainer, HttpKey};
+
+/// How often queues are saved, in case the bot doesn't get to shut down cleanly.
+const SAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Set once the saved queues have been restored. Saving before then would overwrite them with
+/// the empty queues of a bot that just started.
+static RESTORED: AtomicBool = AtomicBool::new(false);
+
+/// A guild's queue as it was when saved.
+#[derive(Serialize, Deserialize, Debug)]
+struct SavedQueue {
+    voice_channel: ChannelId,
+    /// How far into the first track playback was.
+    position: Duration,
+    tracks: Vec<SavedTrack>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct SavedTrack {
+    query: String,
+    /// The exact video, if it had been looked up.
+    url: Option<String>,
+    title: Option<String>,
+    artist: Option<String>,
+    channel: Option<String>,
+    thumbnail: Option<String>,
+    duration: Option<Duration>,
+    requester: UserId,
+    requested_at: Timestamp,
+    channel_id: ChannelId,
+}
+
+impl SavedTrack {
+    fn new(info: &TrackInfo) -> Self {
+        let metadata = &info.metadata;
+
+        Self {
+            query: info.query.clone(),
+            url: metadata.source_url.clone(),
+            title: metadata.title.clone(),
+            artist: metadata.artist.clone(),
+            channel: metadata.channel.clone(),
+            thumbnail: metadata.thumbnail.clone(),
+            duration: metadata.duration,
+            requester: info.requester,
+            requested_at: info.requested_at,
+            channel_id: info.channel_id,
+        }
+    }
+
+    /// A fresh `TrackInfo` for this track, which looks the stream up again when played.
+    fn into_info(self, client: reqwest::Client) -> TrackInfo {
+        let metadata = AuxMetadata {
+            title: self.title,
+            artist: self.artist,
+            channel: self.channel,
+            thumbnail: self.thumbnail,
+            duration: self.duration,
+            source_url: self.url,
+            ..Default::default()
+        };
+
+        let source = match &metadata.source_url {
+            Some(url) => Ytdl::new_custom_meta(Some(metadata.clone()), client, url),
+            None if self.query.starts_with("https") || self.query.starts_with("www.") => {
+                Ytdl::new(client, self.query.clone())
+            }
+            None => Ytdl::new_search(client, self.query.clone()),
+        };
+
+        TrackInfo {
+            metadata,
+            requester: self.requester,
+            requested_at: self.requested_at,
+            query: self.query,
+            channel_id: self.channel_id,
+            source,
+        }
+    }
+}
+
+/// Where queues are kept between runs of the bot, next to the config so every instance has its
+/// own, e.g. `config.queues.json` for `config.json`.
+fn queues_path(data: &TypeMap) -> PathBuf {
+    let config_path = data
+        .get::<ConfigContainer>()
+        .expect("Missing Config")
+        .config_path();
+
+    Path::new(config_path).with_extension("queues.json")
+}
+
+/// Writes every guild's queue and voice channel to disk.
+pub async fn save_queues(data: &RwLock<TypeMap>) -> std::io::Result<()> {
+    if !RESTORED.load(Ordering::SeqCst) {
+        return Ok(());
+    }
+
+    let (songbird, path) = {
+        let store = data.read().await;
+        (store.get::<SongbirdKey>().cloned(), queues_path(&store))
+    };
+    let Some(songbird) = songbird else {
+        return Ok(());
+    };
+
+    // The iterator holds locks on songbird's map, so don't keep it across awaits
+    let calls = songbird.iter().collect::<Vec<_>>();
+
+    let mut saved = HashMap::new();
+    for (guild_id, call) in calls {
+        let call = call.lock().await;
+
+        let Some(voice_channel) = call.current_channel() else {
+            continue;
+        };
+        let queue = call.queue().current_queue();
+        let Some(current) = queue.first() else {
+            continue;
+        };
+
+        let position = current
+            .get_info()
+            .await
+            .map(|state| state.position)
+            .unwrap_or_default();
+
+        let mut tracks = Vec::new();
+        for track in &queue {
+            if let Some(info) = track_info(track).await {
+                tracks.push(SavedTrack::new(&info));
+            }
+        }
+
+        saved.insert(
+            GuildId::from(guild_id.0),
+            SavedQueue {
+                voice_channel: ChannelId::from(voice_channel.0),
+                position,
+                tracks,
+            },
+        );
+    }
+
+    let temp_path = path.with_extension("json.tmp");
+    let contents = serde_json::to_string_pretty(&saved)?;
+
+    let mut file = File::create(&temp_path)?;
+    file.write_all(contents.as_bytes())?;
+    file.sync_all()?;
+
+    fs::rename(&temp_path, path)
+}
+
+/// Saves the queues every [`SAVE_INTERVAL`].
+pub async fn keep_saving_queues(data: Arc<RwLock<TypeMap>>) {
+    let mut interval = tokio::time::interval(SAVE_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        if let Err(e) = save_queues(&data).await {
+            println!("Failed to save queues: {e}");
+        }
+    }
+}
+
+/// Rejoins the voice channels saved by an earlier run and queues their tracks again, picking
+/// the first one up about where it stopped. Only does anything the first time it's called.
+pub async fn restore_queues(ctx: &Context) {
+    if RESTORED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    let path = queues_path(&*ctx.data.read().await);
+
+    let saved = match fs::read_to_string(path) {
+        Ok(text) => {
+            serde_json::from_str::<HashMap<GuildId, SavedQueue>>(&text).unwrap_or_else(|e| {
+                println!("Failed to read saved queues: {e}");
+                HashMap::new()
+            })
+        }
+        Err(_) => HashMap::new(),
+    };
+
+    let songbird = songbird::get(ctx)
+        .await
+        .expect("Songbird Voice client placed in at initialisation.");
+    let client = ctx
+        .data
+        .read()
+        .await
+        .get::<HttpKey>()
+        .cloned()
+        .expect("Guaranteed to exist in the typemap.");
+
+    for (guild_id, queue) in saved {
+        if queue.tracks.is_empty() {
+            continue;
+        }
+
+        let call = match songbird.join(guild_id, queue.voice_channel).await {
+            Ok(call) => call,
+            Err(e) => {
+                println!(
+                    "Failed to rejoin {} in {guild_id}: {e}",
+                    queue.voice_channel
+                );
+                continue;
+            }
+        };
+        add_call_events(ctx, guild_id, &call).await;
+
+        if let Some(reason) = take_stage(&ctx.cache, &ctx.http, guild_id, queue.voice_channel).await
+        {
+            println!("Rejoined {} in {guild_id}: {reason}", queue.voice_channel);
+        }
+
+        let volume = volume_scale(guild_settings(ctx, Some(guild_id)).await.default_volume);
+        let mut call = call.lock().await;
+
+        let count = queue.tracks.len();
+        for (i, track) in queue.tracks.into_iter().enumerate() {
+            let handle = enqueue(&mut call, track.into_info(client.clone()), volume).await;
+
+            if i == 0 && !queue.position.is_zero() {
+                let _ = handle.seek(queue.position);
+            }
+        }
+
+        println!("Restored {count} songs in {guild_id}");
+    }
+}
@@ -0,0 +1,135 @@
+use serenity::all::{
+    Cache, ChannelId, ChannelType, CreateStageInstance, EditStageInstance, EditVoiceState,
+    GuildChannel, GuildId, Http, Permissions,
+};
+use serenity::async_trait;
+use songbird::tracks::TrackHandle;
+use songbird::{Call, Event, EventContext, EventHandler};
+use tokio::sync::Mutex;
+
+use std::sync::Arc;
+
+use crate::commands::music_util::track_info;
+
+/// Discord's limit on stage topics, in characters.
+const TOPIC_LENGTH: usize = 120;
+
+/// The channel, if it's a Stage, along with what the bot may do in it.
+fn stage_channel(
+    cache: &Cache,
+    guild_id: GuildId,
+    channel_id: ChannelId,
+) -> Option<(GuildChannel, Permissions)> {
+    let guild = cache.guild(guild_id)?;
+
+    let channel = guild.channels.get(&channel_id)?;
+    if channel.kind != ChannelType::Stage {
+        return None;
+    }
+
+    let member = guild.members.get(&cache.current_user().id)?;
+    let permissions = guild.user_permissions_in(channel, member);
+
+    Some((channel.clone(), permissions))
+}
+
+/// Gets the bot heard after joining a Stage: it becomes a speaker if it may, or asks to speak
+/// if not. Returns why nothing can be heard, if that's the case. Other channels are left alone.
+pub async fn take_stage(
+    cache: &Cache,
+    http: &Http,
+    guild_id: GuildId,
+    channel_id: ChannelId,
+) -> Option<String> {
+    let (channel, permissions) = stage_channel(cache, guild_id, channel_id)?;
+
+    if permissions.mute_members() {
+        let speak = EditVoiceState::new().suppress(false);
+
+        return match channel.edit_own_voice_state(http, speak).await {
+            Ok(()) => None,
+            Err(e) => Some(format!(
+                "I couldn't become a speaker in <#{channel_id}>: {e}"
+            )),
+        };
+    }
+
+    if permissions.request_to_speak() {
+        let request = EditVoiceState::new().request_to_speak(true);
+
+        return match channel.edit_own_voice_state(http, request).await {
+            Ok(()) => Some(format!(
+                "I need Mute Members to speak in <#{channel_id}> by myself, so I've asked to \
+                 speak. Nothing can be heard until a stage moderator invites me up"
+            )),
+            Err(e) => Some(format!("I couldn't ask to speak in <#{channel_id}>: {e}")),
+        };
+    }
+
+    Some(format!(
+        "I need Mute Members or Request to Speak in <#{channel_id}>, without them nothing I play \
+         can be heard"
+    ))
+}
+
+/// Sets the topic of a Stage to `track`'s title. Does nothing in other channels, or without the
+/// permissions to moderate the Stage.
+pub async fn show_track_as_topic(
+    cache: &Cache,
+    http: &Http,
+    guild_id: GuildId,
+    channel_id: ChannelId,
+    track: &TrackHandle,
+) {
+    let Some((_, permissions)) = stage_channel(cache, guild_id, channel_id) else {
+        return;
+    };
+
+    // Stage moderators need all three, without them Discord refuses anyway
+    if !permissions.contains(
+        Permissions::MANAGE_CHANNELS | Permissions::MUTE_MEMBERS | Permissions::MOVE_MEMBERS,
+    ) {
+        return;
+    }
+
+    let Some(info) = track_info(track).await else {
+        return;
+    };
+    let topic = info.title().chars().take(TOPIC_LENGTH).collect::<String>();
+
+    // A Stage only has an instance while it's live, start one if it isn't
+    let edited = channel_id
+        .edit_stage_instance(http, EditStageInstance::new().topic(&topic))
+        .await;
+
+    if edited.is_err() {
+        if let Err(e) = channel_id
+            .create_stage_instance(http, CreateStageInstance::new(topic))
+            .await
+        {
+            println!("Failed to set the stage topic: {e}");
+        }
+    }
+}
+
+/// Sets the topic of a Stage to the title of each track as it starts.
+pub struct StageTopicHandler {
+    pub call: Arc<Mutex<Call>>,
+    pub guild_id: GuildId,
+    pub cache: Arc<Cache>,
+    pub http: Arc<Http>,
+}
+
+#[async_trait]
+impl EventHandler for StageTopicHandler {
+    async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
+        let EventContext::Track([(_, track), ..]) = ctx else {
+            return None;
+        };
+
+        let channel_id = ChannelId::from(self.call.lock().await.current_channel()?.0);
+        show_track_as_topic(&self.cache, &self.http, self.guild_id, channel_id, track).await;
+
+        None
+    }
+}
@@ -15,9 +15,15 @@ impl TypeMapKey for HttpKey {
 }
 
 impl TypeMapKey for TrackMetaKey {
-    type Value = songbird::input::AuxMetadata;
+    type Value = crate::commands::music_util::TrackInfo;
 }
 
 impl TypeMapKey for ShardManagerContainer {
     type Value = std::sync::Arc<serenity::all::ShardManager>;
 }
+
+pub struct PlaybackContainer;
+
+impl TypeMapKey for PlaybackContainer {
+    type Value = crate::util::playback::PlaybackStates;
+}